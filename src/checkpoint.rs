@@ -0,0 +1,51 @@
+//! saving and loading the state of a run part way through, so that long runs can be resumed
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{optimizer::Shifts, triangle::Triangles};
+
+/// the state of a [`Fitter`](crate::Fitter) between iterations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint<M = Triangles> {
+    /// number of completed iterations
+    pub iteration: usize,
+    /// seed that the per-iteration RNGs are derived from
+    pub seed: u64,
+    pub tris: M,
+    /// the shift each vertex is at, with an adaptive search pattern. missing from checkpoints made before this was
+    /// saved, which then start again from the full shift
+    #[serde(default)]
+    pub shifts: Shifts,
+}
+
+impl<M: Serialize + DeserializeOwned> Checkpoint<M> {
+    /// writes the checkpoint to `path`.
+    ///
+    /// this writes to a temporary file first and then renames it over `path`, so
+    /// killing the program part way through saving will not destroy the last checkpoint.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(
+            File::create(&tmp_path).with_context(|| format!("failed to create {tmp_path:?}"))?,
+        );
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp_path, path).with_context(|| format!("failed to move checkpoint to {path:?}"))?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path).with_context(|| format!("failed to open {path:?}"))?);
+        serde_json::from_reader(reader).with_context(|| format!("{path:?} is not a valid checkpoint"))
+    }
+}
@@ -18,6 +18,7 @@ mod basic_colors {
     pub const WHITE: Color = [1.0; 4];
     pub const TRANSPARENT: Color = [0.0; 4];
 }
+#[allow(clippy::module_inception)]
 mod colors {
     use super::basic_colors::Color;
 
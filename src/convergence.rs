@@ -0,0 +1,194 @@
+//! deciding when a fit is done, other than by running out of iterations. see [`Convergence`]
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{fit::StepReport, metrics::Quality, FitConfig};
+
+/// rules for stopping a fit before all of the iterations are done. it stops as soon as any of the ones that are
+/// set are met (or nothing changes, if [`FitConfig::exit_early`] is set)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Convergence {
+    /// stop once an iteration moves no more than this fraction of the verticies
+    pub min_moved: Option<f64>,
+    /// stop once the score of the whole mesh has gotten better by less than this fraction (of the score) over the
+    /// last `window` iterations
+    pub min_improvement: Option<f64>,
+    pub window: usize,
+    /// stop once fitting has taken this long (checked after each iteration, so it can go over by up to one)
+    pub time_limit: Option<Duration>,
+    /// stop once the PSNR (in dB) of the mesh is at least this
+    pub target_psnr: Option<f64>,
+    /// stop once the SSIM of the mesh is at least this
+    pub target_ssim: Option<f64>,
+}
+
+impl Default for Convergence {
+    fn default() -> Self {
+        Self {
+            min_moved: None,
+            min_improvement: None,
+            window: 5,
+            time_limit: None,
+            target_psnr: None,
+            target_ssim: None,
+        }
+    }
+}
+
+impl Convergence {
+    pub fn min_moved(mut self, min_moved: Option<f64>) -> Self {
+        self.min_moved = min_moved;
+        self
+    }
+
+    pub fn min_improvement(mut self, min_improvement: Option<f64>, window: usize) -> Self {
+        self.min_improvement = min_improvement;
+        self.window = window;
+        self
+    }
+
+    pub fn time_limit(mut self, time_limit: Option<Duration>) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    pub fn target_psnr(mut self, target_psnr: Option<f64>) -> Self {
+        self.target_psnr = target_psnr;
+        self
+    }
+
+    pub fn target_ssim(mut self, target_ssim: Option<f64>) -> Self {
+        self.target_ssim = target_ssim;
+        self
+    }
+
+    /// if the quality of the mesh has to be measured to check this
+    pub fn needs_quality(&self) -> bool {
+        self.target_psnr.is_some() || self.target_ssim.is_some()
+    }
+}
+
+/// why a fit stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// all of the iterations were run
+    Finished,
+    /// an iteration did not change the mesh at all, by moving, flipping or refining (with [`FitConfig::exit_early`])
+    NothingMoved,
+    /// an iteration moved no more than [`Convergence::min_moved`] of the verticies
+    FewMoved { fraction: f64 },
+    /// the score got better by less than [`Convergence::min_improvement`] over the window
+    Stalled { improvement: f64, window: usize },
+    /// [`Convergence::time_limit`] ran out
+    TimeLimit { elapsed: Duration },
+    /// the mesh reached [`Convergence::target_psnr`] or [`Convergence::target_ssim`]
+    TargetQuality { psnr: f64, ssim: f64 },
+    /// it was stopped part way through an iteration (by the progress callback of [`Fitter::step`](crate::Fitter::step))
+    Interrupted,
+}
+
+impl StopReason {
+    /// if this is because the mesh has stopped improving (rather than because time or iterations ran out, it is good
+    /// enough, or it was stopped). when fitting a smaller copy of the image first, this means it is time to move on to
+    /// a larger one, not to stop entirely
+    pub fn converged(&self) -> bool {
+        matches!(self, StopReason::NothingMoved | StopReason::FewMoved { .. } | StopReason::Stalled { .. })
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Finished => write!(f, "all iterations done"),
+            StopReason::NothingMoved => write!(f, "nothing changed"),
+            StopReason::FewMoved { fraction } => write!(f, "only {:.2}% of the verticies moved", fraction * 100.0),
+            StopReason::Stalled { improvement, window } => write!(
+                f,
+                "the score only improved by {:.3}% over the last {window} iterations",
+                improvement * 100.0
+            ),
+            StopReason::TimeLimit { elapsed } => write!(f, "time limit reached after {elapsed:.1?}"),
+            StopReason::TargetQuality { psnr, ssim } => write!(f, "target quality reached (PSNR {psnr:.2}dB, SSIM {ssim:.4})"),
+            StopReason::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// keeps track of how a fit is going, to check it against [`FitConfig::convergence`]
+#[derive(Debug, Clone)]
+pub struct Progress {
+    started: Instant,
+    /// score of the whole mesh after each iteration (only if [`Convergence::min_improvement`] is set)
+    scores: Vec<f64>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress {
+    /// starts the clock for [`Convergence::time_limit`]
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            scores: vec![],
+        }
+    }
+
+    /// forgets the scores so far (but not how long it has been going), for when they are no longer comparable to
+    /// new ones (eg: the image being fit has changed size)
+    pub fn reset_scores(&mut self) {
+        self.scores.clear();
+    }
+
+    /// records an iteration, returning why fitting should stop now (if it should).
+    ///
+    /// `score` gives the score of the whole mesh (see [`Fitter::score`](crate::Fitter::score)), and `quality` how close
+    /// it is to the image. they are only called if the rules that need them are set
+    pub fn check(
+        &mut self,
+        cfg: &FitConfig,
+        report: &StepReport,
+        score: impl FnOnce() -> f64,
+        quality: impl FnOnce() -> Quality,
+    ) -> Option<StopReason> {
+        let rules = &cfg.convergence;
+        // flips and refinement change the mesh too, so it has only settled if none of them did anything either
+        if cfg.exit_early && report.moved == 0 && report.flipped == 0 && !report.refinement.changed() {
+            return Some(StopReason::NothingMoved);
+        }
+        if let Some(min_moved) = rules.min_moved {
+            let fraction = report.moved as f64 / report.verts.max(1) as f64;
+            if fraction <= min_moved {
+                return Some(StopReason::FewMoved { fraction });
+            }
+        }
+        if let Some(min_improvement) = rules.min_improvement {
+            self.scores.push(score());
+            let window = rules.window.max(1);
+            if self.scores.len() > window {
+                let (before, now) = (self.scores[self.scores.len() - 1 - window], self.scores[self.scores.len() - 1]);
+                let improvement = (now - before) / before.abs().max(f64::EPSILON);
+                if improvement < min_improvement {
+                    return Some(StopReason::Stalled { improvement, window });
+                }
+            }
+        }
+        if rules.needs_quality() {
+            let Quality { psnr, ssim, .. } = quality();
+            if rules.target_psnr.is_some_and(|target| psnr >= target) || rules.target_ssim.is_some_and(|target| ssim >= target) {
+                return Some(StopReason::TargetQuality { psnr, ssim });
+            }
+        }
+        let elapsed = self.started.elapsed();
+        if rules.time_limit.is_some_and(|limit| elapsed >= limit) {
+            return Some(StopReason::TimeLimit { elapsed });
+        }
+        None
+    }
+}
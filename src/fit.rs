@@ -0,0 +1,789 @@
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::{imageops, GrayImage, RgbImage};
+use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    checkpoint::Checkpoint,
+    convergence::{Convergence, Progress, StopReason},
+    io::scale_image,
+    mesh::{FlipCriterion, Initializer, Mesh, Movement, Refinement},
+    metrics::Quality,
+    optimizer::{MoveInfo, Optimizer, OptimizerKind, SearchPattern, Shifts},
+    scoring::{
+        cache::ScoreCache, gradient::GradientMap, raster::SamplingMode, score, score_for_group, stats::ImageStats, Scorer,
+        ScoringScheme,
+    },
+    triangle::Triangles,
+    vec2::F64x2,
+};
+
+/// settings for fitting a mesh of triangles to an image.
+///
+/// built up with the setter methods, starting from [`FitConfig::default`]
+/// ```no_run
+/// # use trifit::{FitConfig, ScoringScheme};
+/// let cfg = FitConfig::default()
+///     .image_size(900)
+///     .tri_size(15.0)
+///     .iterations(50)
+///     .shift(0.5)
+///     .scoring(ScoringScheme::AvgWithShapeWeight);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FitConfig {
+    /// size of the longest side of the image being fit (the image is scaled to this)
+    pub image_size: u32,
+    /// size of each triangle
+    pub tri_size: f64,
+    /// ammount to move each vertex each iteration
+    pub shift: f64,
+    /// max number of iterations to perform
+    pub iterations: usize,
+    /// chance (1 in `randomness`) that a random move will be picked instead of the best one. 0 disables this.
+    /// only used by the [`Greedy`](crate::optimizer::Greedy) optimizer
+    pub randomness: usize,
+    /// how verticies are moved (see [`FitConfig::optimizing`] and [`FitConfig::optimizer`])
+    pub optimizer: Arc<dyn Optimizer>,
+    /// method of scoring triangles (see [`FitConfig::scoring`] and [`FitConfig::scorer`])
+    pub scorer: Arc<dyn Scorer>,
+    /// how pixels along the edges of triangles are counted when scoring and coloring them
+    pub sampling: SamplingMode,
+    /// number of steps (of size `shift`) to try in each direction
+    pub steps: u32,
+    /// which directions to step in (and how), see [`SearchPattern`]
+    pub search: SearchPattern,
+    /// stop once an iteration makes no changes
+    pub exit_early: bool,
+    /// other rules for stopping before all of the iterations are done
+    pub convergence: Convergence,
+    /// seed for all random choices made while fitting. runs with the same seed (and settings) give the same result.
+    /// if this is `None`, a random seed is picked
+    pub seed: Option<u64>,
+    /// number of threads to optimize verticies on. 0 uses all available cores, and 1 runs everything on the current thread.
+    /// this only changes how fast it is, the results are the same either way
+    pub threads: usize,
+    /// if set, the mesh is refined after every iteration (splitting triangles where there is detail, and merging
+    /// them where there is not), keeping it to at most this many triangles. only meshes that can change shape
+    /// (like [`TriMesh`](crate::mesh::TriMesh)) can be refined, the grid ignores this
+    pub max_triangles: Option<usize>,
+    /// if set, edges are flipped after every iteration when it makes the triangles better by this measure,
+    /// which cleans up slivers. like `max_triangles` this only does anything for meshes that can change shape
+    pub edge_flips: Option<FlipCriterion>,
+    /// how the starting mesh is laid out. anything other than the regular grid needs a mesh that can
+    /// have any shape (like [`TriMesh`](crate::mesh::TriMesh)), the grid ignores this
+    pub initializer: Initializer,
+    /// let verticies on the outside of the mesh slide along it (see [`Movement`]). if not set (the default)
+    /// they stay where they started
+    pub slide_border: bool,
+    /// number of sizes to fit the image at, each half the size of the last, starting with the smallest. the
+    /// iterations are split evenly between them (with any left over going to the full size image), and the small
+    /// ones are much quicker to fit while still getting the verticies most of the way to where they need to be.
+    /// 1 only fits the full size image
+    pub pyramid_levels: usize,
+}
+
+impl Default for FitConfig {
+    fn default() -> Self {
+        Self {
+            image_size: 500,
+            tri_size: 15.0,
+            shift: 0.5,
+            iterations: 50,
+            randomness: 0,
+            optimizer: OptimizerKind::default().optimizer(),
+            scorer: ScoringScheme::default().scorer(),
+            sampling: SamplingMode::default(),
+            steps: 4,
+            search: SearchPattern::default(),
+            exit_early: false,
+            convergence: Convergence::default(),
+            seed: None,
+            threads: 1,
+            max_triangles: None,
+            edge_flips: None,
+            initializer: Initializer::default(),
+            slide_border: false,
+            pyramid_levels: 1,
+        }
+    }
+}
+
+impl FitConfig {
+    pub fn image_size(mut self, image_size: u32) -> Self {
+        self.image_size = image_size;
+        self
+    }
+
+    pub fn tri_size(mut self, tri_size: f64) -> Self {
+        self.tri_size = tri_size;
+        self
+    }
+
+    pub fn shift(mut self, shift: f64) -> Self {
+        self.shift = shift;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn randomness(mut self, randomness: usize) -> Self {
+        self.randomness = randomness;
+        self
+    }
+
+    /// uses one of the built in optimizers, with its default settings
+    pub fn optimizing(mut self, kind: OptimizerKind) -> Self {
+        self.optimizer = kind.optimizer();
+        self
+    }
+
+    /// uses a custom optimizer (or a built in one with different settings)
+    pub fn optimizer(mut self, optimizer: impl Optimizer + 'static) -> Self {
+        self.optimizer = Arc::new(optimizer);
+        self
+    }
+
+    /// uses one of the built in scoring schemes
+    pub fn scoring(mut self, scoring: ScoringScheme) -> Self {
+        self.scorer = scoring.scorer();
+        self
+    }
+
+    /// uses a custom scorer
+    pub fn scorer(mut self, scorer: impl Scorer + 'static) -> Self {
+        self.scorer = Arc::new(scorer);
+        self
+    }
+
+    pub fn sampling(mut self, sampling: SamplingMode) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// panics if `steps` is 0, since then there would be nowhere to move to
+    pub fn steps(mut self, steps: u32) -> Self {
+        assert!(steps > 0, "there has to be at least one step in each direction");
+        self.steps = steps;
+        self
+    }
+
+    pub fn search(mut self, search: SearchPattern) -> Self {
+        self.search = search;
+        self
+    }
+
+    pub fn exit_early(mut self, exit_early: bool) -> Self {
+        self.exit_early = exit_early;
+        self
+    }
+
+    pub fn convergence(mut self, convergence: Convergence) -> Self {
+        self.convergence = convergence;
+        self
+    }
+
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn max_triangles(mut self, max_triangles: Option<usize>) -> Self {
+        self.max_triangles = max_triangles;
+        self
+    }
+
+    pub fn edge_flips(mut self, edge_flips: Option<FlipCriterion>) -> Self {
+        self.edge_flips = edge_flips;
+        self
+    }
+
+    pub fn initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
+    }
+
+    pub fn slide_border(mut self, slide_border: bool) -> Self {
+        self.slide_border = slide_border;
+        self
+    }
+
+    pub fn pyramid_levels(mut self, pyramid_levels: usize) -> Self {
+        self.pyramid_levels = pyramid_levels;
+        self
+    }
+
+    /// number of threads to actually use, resolving 0 to the number of cores
+    fn thread_count(&self) -> usize {
+        match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+}
+
+/// fits a grid of triangles to `image`, running untill all iterations are done
+/// (or nothing changes, if `exit_early` is set, or it meets [`FitConfig::convergence`])
+///
+/// the image is first scaled to `cfg.image_size`, so the returned triangles are
+/// in the coordinates of `scale_image(image, cfg.image_size)`
+pub fn fit(image: &RgbImage, cfg: &FitConfig) -> Triangles {
+    fit_mesh(image, cfg)
+}
+
+/// like [`fit`], but for any kind of mesh (starting from [`FitConfig::initializer`]).
+/// use this with a [`TriMesh`](crate::mesh::TriMesh) and [`FitConfig::max_triangles`] for an adaptive mesh
+pub fn fit_mesh<M: Mesh>(image: &RgbImage, cfg: &FitConfig) -> M {
+    let (_, _, scaled, _) = scale_image(image.clone(), cfg.image_size);
+    let mut fitter = Fitter::<M>::for_image(&scaled, cfg.clone());
+    fitter.run();
+    fitter.into_triangles()
+}
+
+/// what happened during one optimizer pass over the mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepReport {
+    /// the iteration that was just completed (starting at 1)
+    pub iteration: usize,
+    /// number of verticies that were moved
+    pub moved: usize,
+    /// number of verticies that could have been
+    pub verts: usize,
+    /// how the mesh was refined afterwards (if [`FitConfig::max_triangles`] is set)
+    pub refinement: Refinement,
+    /// number of edges that were flipped afterwards (if [`FitConfig::edge_flips`] is set)
+    pub flipped: usize,
+    /// time the pass took
+    pub duration: Duration,
+}
+
+/// one of the sizes the image is fit at, see [`FitConfig::pyramid_levels`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    /// how many times the image has been halved, 0 is the full size image
+    pub level: usize,
+    /// size of the image at this level compared to the full size one
+    pub scale: f64,
+    /// width and height of the image at this level
+    pub size: (u32, u32),
+}
+
+/// hooks into [`Fitter::run_with`], to report on (or stop) a run as it goes. every method does nothing by default
+pub trait RunObserver<M: Mesh> {
+    /// called as verticies are completed with (verts done, total verts), and can return `false` to stop the
+    /// run part way through an iteration (see [`Fitter::step`])
+    fn progress(&mut self, _done: usize, _total: usize) -> bool {
+        true
+    }
+
+    /// called after every iteration. `checkpoint` is the state of the fitter, with the mesh scaled back up to the
+    /// full size image if this is a smaller level
+    fn step(&mut self, _level: Level, _report: &StepReport, _checkpoint: &Checkpoint<M>) {}
+
+    /// called when the fitter is done with a level (including the full size one), and why.
+    /// `checkpoint` is like in [`RunObserver::step`]
+    fn level_done(&mut self, _level: Level, _reason: StopReason, _checkpoint: &Checkpoint<M>) {}
+}
+
+impl<M: Mesh> RunObserver<M> for () {}
+
+/// incrementally fits a mesh of triangles (the regular grid, unless otherwise specified) to an (already scaled)
+/// image, one iteration at a time.
+pub struct Fitter<'a, M: Mesh = Triangles> {
+    image: &'a RgbImage,
+    stats: ImageStats,
+    cfg: FitConfig,
+    tris: M,
+    iteration: usize,
+    /// the RNG for each iteration is derived from this and the iteration number,
+    /// so a run can be resumed from a checkpoint and continue as it would have
+    seed: u64,
+    /// groups of verticies that can be optimized in parallel, see [`Mesh::independent_sets`]
+    independent_sets: Option<Vec<Vec<M::Vert>>>,
+    /// scores of triangles that have not changed since they were last scored
+    cache: ScoreCache,
+    /// the shift of each vertex, if the search pattern is adaptive
+    shifts: Shifts,
+    /// kept to be scaled down for the smaller levels of the pyramid
+    weight_map: Option<GrayImage>,
+}
+
+impl<'a> Fitter<'a> {
+    /// creates a fitter starting from a regular grid covering `image`
+    pub fn new(image: &'a RgbImage, cfg: FitConfig) -> Self {
+        let tris = Triangles::for_image(image.width(), image.height(), cfg.tri_size);
+        Self::with_triangles(image, cfg, tris)
+    }
+}
+
+impl<'a, M: Mesh> Fitter<'a, M> {
+    /// creates a fitter starting from the mesh laid out by [`FitConfig::initializer`]
+    pub fn for_image(image: &'a RgbImage, cfg: FitConfig) -> Self {
+        let seed = cfg.seed.unwrap_or_else(rand::random);
+        let tris = M::initial(image, &cfg, seed);
+        let mut fitter = Self::with_triangles(image, cfg, tris);
+        fitter.seed = seed;
+        fitter
+    }
+
+    /// creates a fitter starting from an existing mesh
+    pub fn with_triangles(image: &'a RgbImage, cfg: FitConfig, tris: M) -> Self {
+        let seed = cfg.seed.unwrap_or_else(rand::random);
+        Self {
+            image,
+            stats: ImageStats::in_space(image, cfg.scorer.color_space()).sampling(cfg.sampling),
+            cfg,
+            cache: ScoreCache::new(tris.face_index_bound()),
+            tris,
+            iteration: 0,
+            seed,
+            independent_sets: None,
+            shifts: Shifts::default(),
+            weight_map: None,
+        }
+    }
+
+    /// creates a fitter that continues on from where a checkpoint left off
+    pub fn resume(image: &'a RgbImage, cfg: FitConfig, checkpoint: Checkpoint<M>) -> Self {
+        Self {
+            image,
+            stats: ImageStats::in_space(image, cfg.scorer.color_space()).sampling(cfg.sampling),
+            cfg,
+            cache: ScoreCache::new(checkpoint.tris.face_index_bound()),
+            tris: checkpoint.tris,
+            iteration: checkpoint.iteration,
+            seed: checkpoint.seed,
+            independent_sets: None,
+            shifts: checkpoint.shifts,
+            weight_map: None,
+        }
+    }
+
+    /// uses an already computed gradient of the image, for scorers that need it.
+    /// if this is not given it is computed on the first step (if the scorer needs it)
+    pub fn with_gradient(mut self, gradient: Arc<GradientMap>) -> Self {
+        self.stats = self.stats.with_gradient(gradient);
+        self
+    }
+
+    /// weights how much each pixel matters by a grayscale mask the same size as the image (see [`ImageStats::with_weight_map`]),
+    /// so the optimizer spends its effort where the mask is bright
+    pub fn with_weight_map(mut self, map: &GrayImage) -> Self {
+        self.stats.set_weight_map(map);
+        self.cache = ScoreCache::new(self.tris.face_index_bound());
+        self.weight_map = Some(map.clone());
+        self
+    }
+
+    /// the current state of the fitter, to be resumed later with [`Fitter::resume`]
+    pub fn checkpoint(&self) -> Checkpoint<M> {
+        Checkpoint {
+            iteration: self.iteration,
+            seed: self.seed,
+            tris: self.tris.clone(),
+            shifts: self.shifts.clone(),
+        }
+    }
+
+    /// the image being fit to
+    pub fn image(&self) -> &'a RgbImage {
+        self.image
+    }
+
+    /// color stats of the image being fit to, in the color space of the scoring scheme
+    pub fn stats(&self) -> &ImageStats {
+        &self.stats
+    }
+
+    pub fn config(&self) -> &FitConfig {
+        &self.cfg
+    }
+
+    pub fn triangles(&self) -> &M {
+        &self.tris
+    }
+
+    pub fn into_triangles(self) -> M {
+        self.tris
+    }
+
+    /// the seed in use, which can be passed to [`FitConfig::seed`] to reproduce this run
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// number of completed iterations
+    pub fn iteration(&self) -> usize {
+        self.iteration
+    }
+
+    /// if all of the configured iterations have been run
+    pub fn is_done(&self) -> bool {
+        self.iteration >= self.cfg.iterations
+    }
+
+    /// runs a single optimizer pass over every vertex (in a random order), and then refines the mesh
+    /// if [`FitConfig::max_triangles`] is set.
+    ///
+    /// `progress` is called as verticies are completed with (verts done, total verts), and can return `false`
+    /// to stop part way through, in which case `None` is returned.
+    pub fn step(&mut self, progress: impl FnMut(usize, usize) -> bool) -> Option<StepReport> {
+        let starttime = Instant::now();
+        if self.cfg.scorer.needs_gradient() && self.stats.gradient().is_none() {
+            self.stats.set_gradient(Arc::new(GradientMap::new(self.image)));
+        }
+
+        let threads = self.cfg.thread_count();
+        let verts = self.tris.verts().len();
+        let moved = self.step_sets(threads, progress)?;
+        let refinement = match self.cfg.max_triangles {
+            Some(max_triangles) => self.tris.refine(&self.stats, &self.cfg, max_triangles),
+            None => Refinement::default(),
+        };
+        let flipped = match self.cfg.edge_flips {
+            Some(criterion) => self.tris.flip_edges(&self.stats, &self.cfg, criterion),
+            None => 0,
+        };
+        if refinement.changed() || flipped > 0 {
+            // every vertex and face may have been renumbered (or at least have different neighbours)
+            self.cache = ScoreCache::new(self.tris.face_index_bound());
+            self.independent_sets = None;
+        }
+        if refinement.changed() {
+            self.shifts = Shifts::default();
+        }
+        self.iteration += 1;
+
+        Some(StepReport {
+            iteration: self.iteration,
+            moved,
+            verts,
+            refinement,
+            flipped,
+            duration: starttime.elapsed(),
+        })
+    }
+
+    /// optimizes each independent set of verticies in turn, splitting the set between threads.
+    ///
+    /// no vertex in a set is part of any triangle around another vertex in that set, so
+    /// the best moves can all be found from the same mesh, and then applied afterwards.
+    /// this is done the same way with one thread, so results never depend on how many there are
+    fn step_sets(&mut self, threads: usize, mut progress: impl FnMut(usize, usize) -> bool) -> Option<usize> {
+        let mut rng = self.iteration_rng();
+        let sets = self
+            .independent_sets
+            .get_or_insert_with(|| self.tris.independent_sets());
+        let len = sets.iter().map(Vec::len).sum();
+        let mut order = (0..sets.len()).collect::<Vec<_>>();
+        order.shuffle(&mut rng);
+        // each vertex gets its own RNG, so results do not depend on the number of threads
+        let base_seed: u64 = rng.gen();
+
+        let mut done = 0;
+        let mut moved = 0;
+        for set in order {
+            let verts = &sets[set];
+            let (stats, tris, cfg, cache, shifts) = (&self.stats, &self.tris, &self.cfg, &self.cache, &self.shifts);
+            let iteration = self.iteration;
+            let best_moves = move |chunk: &[M::Vert]| {
+                chunk
+                    .iter()
+                    .map(|&v| {
+                        let key = tris.vert_key(v);
+                        let mut rng = vertex_rng(base_seed, key);
+                        let shift = shifts.get(cfg, key);
+                        (v, best_move(stats, tris, v, cfg, iteration, shift, cache, &mut rng))
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let moves = if threads == 1 {
+                best_moves(verts)
+            } else {
+                thread::scope(|scope| {
+                    verts
+                        .chunks(verts.len().div_ceil(threads).max(1))
+                        .map(|chunk| scope.spawn(move || best_moves(chunk)))
+                        .collect::<Vec<_>>()
+                        .into_iter()
+                        .flat_map(|handle| handle.join().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            };
+            for (v, pos) in moves {
+                if let Some(pos) = pos {
+                    self.tris.set_vert(v, pos);
+                    self.cache.vertex_moved(&self.tris, v);
+                    moved += 1;
+                }
+                self.shifts.update(&self.cfg, self.tris.vert_key(v), pos.is_some());
+            }
+            done += verts.len();
+            if !progress(done, len) {
+                return None;
+            }
+        }
+        Some(moved)
+    }
+
+    fn iteration_rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (self.iteration as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    /// the score of the whole mesh ([`Scorer::value`] of every triangle combined), larger is better
+    pub fn score(&self) -> f64 {
+        let scorer = &*self.cfg.scorer;
+        scorer.value(&score_for_group(&self.stats, &self.tris.triangles(), self.cfg.tri_size, scorer))
+    }
+
+    /// steps untill all iterations are done, or (if `exit_early` is set) nothing changes, or it meets
+    /// [`FitConfig::convergence`]. returns which of those it was.
+    ///
+    /// if [`FitConfig::pyramid_levels`] is more than 1 the smaller levels are fit first
+    pub fn run(&mut self) -> StopReason {
+        self.run_with(&mut ())
+    }
+
+    /// like [`Fitter::run`], but reporting on how it is going to `observer`
+    pub fn run_with(&mut self, observer: &mut impl RunObserver<M>) -> StopReason {
+        // the time limit covers every level
+        let mut progress = Progress::new();
+        // quality is always measured against the full size image, so targets are not met early by the small levels
+        let fill = self.cfg.convergence.needs_quality().then(|| ImageStats::new(self.image));
+        let levels = self.cfg.pyramid_levels.max(1);
+        for level in (1..levels).rev() {
+            // each level gets an even share of the iterations, with any left over going to the full size image.
+            // if it converges early the next one picks up where it left off
+            let end = (levels - level) * (self.cfg.iterations / levels);
+            if self.iteration >= end {
+                // already past this level (when resuming from a checkpoint)
+                continue;
+            }
+            let scale = 0.5f64.powi(level as i32);
+            let size = (
+                ((self.image.width() as f64 * scale).round() as u32).max(1),
+                ((self.image.height() as f64 * scale).round() as u32).max(1),
+            );
+            let level = Level { level, scale, size };
+            let image = imageops::resize(self.image, size.0, size.1, imageops::Triangle);
+            let cfg = self
+                .cfg
+                .clone()
+                .tri_size(self.cfg.tri_size * scale)
+                .iterations(end)
+                .pyramid_levels(1);
+            let mut checkpoint = self.checkpoint();
+            checkpoint.tris.scale(scale);
+            let mut fitter = Fitter::resume(&image, cfg, checkpoint);
+            if let Some(map) = &self.weight_map {
+                fitter = fitter.with_weight_map(&imageops::resize(map, size.0, size.1, imageops::Triangle));
+            }
+            let reason = fitter.run_level(level, self.image, fill.as_ref(), &mut progress, observer);
+            let mut checkpoint = fitter.checkpoint();
+            checkpoint.tris.scale(1.0 / scale);
+            observer.level_done(level, reason, &checkpoint);
+            self.restore(checkpoint);
+            if !matches!(reason, StopReason::Finished) && !reason.converged() {
+                return reason;
+            }
+        }
+
+        let level = Level {
+            level: 0,
+            scale: 1.0,
+            size: self.image.dimensions(),
+        };
+        let image = self.image;
+        let reason = self.run_level(level, image, fill.as_ref(), &mut progress, observer);
+        observer.level_done(level, reason, &self.checkpoint());
+        reason
+    }
+
+    /// steps this fitter (which is fitting `level` of the pyramid) untill it is done. the mesh is scaled back up
+    /// to measure its quality against `full_image`
+    fn run_level(
+        &mut self,
+        level: Level,
+        full_image: &RgbImage,
+        fill: Option<&ImageStats>,
+        progress: &mut Progress,
+        observer: &mut impl RunObserver<M>,
+    ) -> StopReason {
+        // scores at different sizes can not be compared
+        progress.reset_scores();
+        while !self.is_done() {
+            let Some(report) = self.step(|done, total| observer.progress(done, total)) else {
+                return StopReason::Interrupted;
+            };
+            let mut checkpoint = self.checkpoint();
+            checkpoint.tris.scale(1.0 / level.scale);
+            observer.step(level, &report, &checkpoint);
+            let reason = progress.check(
+                &self.cfg,
+                &report,
+                || self.score(),
+                || Quality::measure(&checkpoint.tris, fill.unwrap(), full_image),
+            );
+            if let Some(reason) = reason {
+                return reason;
+            }
+        }
+        StopReason::Finished
+    }
+
+    /// picks up from `checkpoint` (made by a fitter for the same image), keeping everything else
+    fn restore(&mut self, checkpoint: Checkpoint<M>) {
+        self.cache = ScoreCache::new(checkpoint.tris.face_index_bound());
+        self.independent_sets = None;
+        self.tris = checkpoint.tris;
+        self.iteration = checkpoint.iteration;
+        self.shifts = checkpoint.shifts;
+    }
+}
+
+/// `key` is [`Mesh::vert_key`]
+fn vertex_rng(base_seed: u64, key: u64) -> StdRng {
+    StdRng::seed_from_u64(base_seed ^ key.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// finds a new optimal position for a vertex in the mesh of triangles. returns if the vertex was moved
+#[allow(clippy::too_many_arguments)]
+pub fn optimize_one<M: Mesh>(
+    stats: &ImageStats,
+    tris: &mut M,
+    v: M::Vert,
+    cfg: &FitConfig,
+    iteration: usize,
+    shift: f64,
+    cache: &mut ScoreCache,
+    rng: &mut impl Rng,
+) -> bool {
+    match best_move(stats, tris, v, cfg, iteration, shift, cache, rng) {
+        Some(pos) => {
+            tris.set_vert(v, pos);
+            cache.vertex_moved(tris, v);
+            true
+        }
+        None => false,
+    }
+}
+
+/// finds where [`FitConfig::optimizer`] wants to move a vertex to (during `iteration`, starting at 0, stepping by
+/// `shift`), without moving it. returns `None` if it should stay where it is
+///
+/// `cache` is used for the score of the triangles around the vertex where it is now
+#[allow(clippy::too_many_arguments)]
+pub fn best_move<M: Mesh>(
+    stats: &ImageStats,
+    tris: &M,
+    v: M::Vert,
+    cfg: &FitConfig,
+    iteration: usize,
+    shift: f64,
+    cache: &ScoreCache,
+    rng: &mut impl Rng,
+) -> Option<F64x2> {
+    // edge verts can only slide along the edge (and corners can not move at all)
+    let along = match tris.movement(v) {
+        Movement::Free => None,
+        Movement::Along(dir) if cfg.slide_border => Some(dir),
+        Movement::Along(_) | Movement::Fixed => return None,
+    };
+    // get the triangles around the current point (the point itself is always the first vertex of each)
+    let (indices, group): (Vec<_>, Vec<_>) = tris.faces_around(v).into_iter().unzip();
+    // and score for that group (these have not moved, so can come from the cache)
+    let scorer = &*cfg.scorer;
+    let original_score = scorer.value(&scorer.combine(
+        &indices
+            .iter()
+            .zip(&group)
+            .map(|(i, t)| *cache.get_or_score(*i, || score(*t, stats, cfg.tri_size, scorer)))
+            .collect::<Vec<_>>(),
+    ));
+    let info = MoveInfo {
+        stats,
+        cfg,
+        group: &group,
+        original: tris.vert(v),
+        original_score,
+        along,
+        keep_winding: tris.keeps_winding(),
+        iteration,
+        shift,
+    };
+    cfg.optimizer.pick(&info, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::Rgb;
+
+    use super::*;
+    use crate::mesh::{Initializer, TriMesh};
+
+    /// checks that no two verticies in the same set are corners of the same triangle (so moving them at the same
+    /// time can not change the same score twice), and that every vertex that can move is in exactly one set
+    fn assert_independent<M: Mesh>(tris: &M)
+    where
+        M::Vert: std::hash::Hash,
+    {
+        let mut seen = HashMap::new();
+        for (s, set) in tris.independent_sets().into_iter().enumerate() {
+            let mut faces = HashMap::new();
+            for v in set {
+                assert!(seen.insert(v, s).is_none(), "{v:?} is in more than one set");
+                for (face, _) in tris.faces_around(v) {
+                    if let Some(other) = faces.insert(face, v) {
+                        panic!("{v:?} and {other:?} are in the same set, but are both corners of face {face}");
+                    }
+                }
+            }
+        }
+        for v in tris.verts() {
+            if tris.movement(v) != Movement::Fixed {
+                assert!(seen.contains_key(&v), "{v:?} can move, but is not in any set");
+            }
+        }
+    }
+
+    #[test]
+    fn grid_sets_are_independent() {
+        assert_independent(&Triangles::for_image(90, 60, 10.0));
+        assert_independent(&Triangles::for_image(97, 41, 7.5));
+    }
+
+    #[test]
+    fn trimesh_sets_are_independent() {
+        let image = RgbImage::from_fn(90, 60, |x, y| Rgb([(x * 2) as u8, (y * 4) as u8, 100]));
+        assert_independent(&TriMesh::from(&Triangles::for_image(90, 60, 10.0)));
+        for initializer in [Initializer::Jitter, Initializer::Poisson] {
+            let cfg = FitConfig::default().tri_size(10.0).initializer(initializer);
+            assert_independent(&TriMesh::initial(&image, &cfg, 7));
+        }
+    }
+
+    #[test]
+    fn results_do_not_depend_on_threads() {
+        let image = RgbImage::from_fn(90, 60, |x, y| Rgb([(x * 2) as u8, (y * 4) as u8, ((x * y) % 256) as u8]));
+        let cfg = FitConfig::default().image_size(90).tri_size(10.0).iterations(3).shift(2.0).seed(Some(7));
+        let one = fit(&image, &cfg.clone().threads(1));
+        assert_ne!(one, Triangles::for_image(90, 60, 10.0));
+        for threads in [2, 3] {
+            assert_eq!(fit(&image, &cfg.clone().threads(threads)), one, "{threads} threads");
+        }
+    }
+}
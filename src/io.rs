@@ -1,16 +1,32 @@
 use std::{cmp, fs::OpenOptions, io::Write, path::PathBuf};
 
-use image::{DynamicImage, GenericImage, Rgb, RgbImage, RgbaImage};
+use anyhow::Context;
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImage, GrayImage, Rgb, RgbImage, RgbaImage};
 
 use crate::{
-    scoring::{average, get_color_in_triangle},
-    triangle::{RelVertPos, Triangle, Triangles},
-    OutputFormat,
+    mesh::Mesh,
+    mesh_format::{self, MeshFile},
+    scoring::stats::ImageStats,
+    triangle::Triangle,
 };
 
-pub fn save(
-    tris: &Triangles,
-    image: &RgbImage,
+#[derive(Debug, Clone, ValueEnum)]
+pub enum OutputFormat {
+    Svg,
+    Image,
+    Mindustry,
+    /// the optimized mesh itself, which can be loaded again with `--mesh-input`. saved as JSON if the
+    /// output file ends in `.json`, and in the compact binary format otherwise (see [`crate::mesh_format`])
+    Mesh,
+    /// temporary mode for input/output GIF rendering. does not support display mode. input file must be a gif
+    GifThroughput,
+}
+
+/// `stats` is the image the triangles were fit to (and how to sample it), which their colors are taken from
+pub fn save<M: Mesh + MeshFile>(
+    tris: &M,
+    stats: &ImageStats,
     image_size: u32,
     out_file: PathBuf,
     format: OutputFormat,
@@ -18,7 +34,7 @@ pub fn save(
 ) {
     match format {
         OutputFormat::Svg => {
-            let svg = make_svg(tris, image, image_size, tracing_mode);
+            let svg = make_svg(tris, stats, image_size, tracing_mode);
             OpenOptions::new()
                 .create(true)
                 .write(true)
@@ -29,43 +45,18 @@ pub fn save(
                 .unwrap();
         }
         OutputFormat::Image => {
-            render_image(tris, image, image_size, tracing_mode)
+            render_image(tris, stats, image_size, tracing_mode)
                 .save(&out_file)
                 .unwrap();
         }
         OutputFormat::Mindustry => {
-            type Inst = (Rgb<u8>, Triangle);
             let mut map = std::collections::HashMap::<Rgb<u8>, Vec<Triangle>>::new();
             for (rgb, tri) in tris
-                .clone()
-                .into_iter_verts()
-                .map(|v| [(true, v), (false, v)])
-                .flatten()
-                .map::<Option<Inst>, _>(|(flipflop, (rx, ry, tri))| {
-                    let (rx1, ry1): (u32, u32);
-                    let (rx2, ry2): (u32, u32);
-                    if flipflop {
-                        (rx1, ry1) = tris.pos_rel(rx, ry, RelVertPos::DownRight)?;
-                        (rx2, ry2) = tris.pos_rel(rx, ry, RelVertPos::DownLeft)?;
-                    } else {
-                        (rx1, ry1) = tris.pos_rel(rx, ry, RelVertPos::Right)?;
-                        (rx2, ry2) = tris.pos_rel(rx, ry, RelVertPos::DownRight)?;
-                    }
-                    let verts = (tri, *tris.get_vert(rx1, ry1), *tris.get_vert(rx2, ry2));
-                    let color = average(&get_color_in_triangle(
-                        image,
-                        Triangle(verts.0, verts.1, verts.2),
-                    ));
-                    Some((color, Triangle(verts.0, verts.1, verts.2)))
-                })
-                .filter(|i| i.is_some())
-                .map(|i| i.unwrap())
+                .triangles()
+                .into_iter()
+                .map(|tri| (stats.color(tri).unwrap_or(Rgb([0; 3])), tri))
             {
-                if map.contains_key(&rgb) {
-                    map.get_mut(&rgb).unwrap().push(tri);
-                } else {
-                    map.insert(rgb, vec![tri]);
-                }
+                map.entry(rgb).or_default().push(tri);
             }
 
             let path = out_file.with_extension("").to_str().unwrap().to_string();
@@ -83,12 +74,12 @@ pub fn save(
                 count += 1;
                 icount += 1;
                 for mut tri in locations {
-                    tri.0.y = image.height() as f64 - tri.0.y
-                        + ((image_size - image.height()) as f64 / 2.0);
-                    tri.1.y = image.height() as f64 - tri.1.y
-                        + ((image_size - image.height()) as f64 / 2.0);
-                    tri.2.y = image.height() as f64 - tri.2.y
-                        + ((image_size - image.height()) as f64 / 2.0);
+                    tri.0.y = stats.height() as f64 - tri.0.y
+                        + ((image_size - stats.height()) as f64 / 2.0);
+                    tri.1.y = stats.height() as f64 - tri.1.y
+                        + ((image_size - stats.height()) as f64 / 2.0);
+                    tri.2.y = stats.height() as f64 - tri.2.y
+                        + ((image_size - stats.height()) as f64 / 2.0);
                     // println!("{x} {y}");
                     res.push_str(&format!(
                         "draw triangle {} {} {} {} {} {}\n",
@@ -105,7 +96,7 @@ pub fn save(
                             .truncate(true)
                             .open(format!("{path}{fcount}.mlog"))
                             .unwrap()
-                            .write_all(&res.as_bytes())
+                            .write_all(res.as_bytes())
                             .unwrap();
                         res.clear();
                         res.push_str(&format!(
@@ -136,20 +127,23 @@ pub fn save(
                 .truncate(true)
                 .open(format!("{path}{fcount}.mlog"))
                 .unwrap()
-                .write_all(&res.as_bytes())
+                .write_all(res.as_bytes())
                 .unwrap();
         }
+        OutputFormat::Mesh => {
+            mesh_format::save(tris, &out_file).unwrap();
+        }
         OutputFormat::GifThroughput => unreachable!(),
     }
     println!("Saved to {out_file:?}");
 }
 
-pub fn render_image(tris: &Triangles, image: &RgbImage, image_size: u32, tracing_mode: bool) -> RgbaImage {
-    let svg = make_svg(tris, image, image_size, tracing_mode); // lies and deceit! (its svgs all the way down)
+pub fn render_image(tris: &impl Mesh, stats: &ImageStats, image_size: u32, tracing_mode: bool) -> RgbaImage {
+    let svg = make_svg(tris, stats, image_size, tracing_mode); // lies and deceit! (its svgs all the way down)
     let tree = usvg::Tree::from_str(&svg, &usvg::Options::default().to_ref()).unwrap();
-    let mut bytes = vec![0u8; (image.width() * image.height() * 4) as usize];
+    let mut bytes = vec![0u8; (stats.width() * stats.height() * 4) as usize];
     let pixmap =
-        tiny_skia::PixmapMut::from_bytes(bytes.as_mut_slice(), image.width(), image.height())
+        tiny_skia::PixmapMut::from_bytes(bytes.as_mut_slice(), stats.width(), stats.height())
             .unwrap();
     resvg::render(
         &tree,
@@ -157,60 +151,54 @@ pub fn render_image(tris: &Triangles, image: &RgbImage, image_size: u32, tracing
         tiny_skia::Transform::default(),
         pixmap,
     );
-    RgbaImage::from_vec(image.width(), image.height(), bytes).unwrap()
+    RgbaImage::from_vec(stats.width(), stats.height(), bytes).unwrap()
 }
 
-pub fn make_svg(tris: &Triangles, image: &RgbImage, image_size: u32, tracing_mode: bool /* output black&white tracing mesh */) -> String {
+pub fn make_svg(tris: &impl Mesh, stats: &ImageStats, image_size: u32, tracing_mode: bool /* output black&white tracing mesh */) -> String {
     use svg::{node::element::Polygon, Document};
 
     let nodes = tris
-        .clone()
-        .into_iter_verts()
-        .map(|v| [(true, v), (false, v)])
-        .flatten()
-        .map::<Option<Polygon>, _>(|(flipflop, (rx, ry, tri))| {
-            let (rx1, ry1): (u32, u32);
-            let (rx2, ry2): (u32, u32);
-            if flipflop {
-                (rx1, ry1) = tris.pos_rel(rx, ry, RelVertPos::DownRight)?;
-                (rx2, ry2) = tris.pos_rel(rx, ry, RelVertPos::DownLeft)?;
-            } else {
-                (rx1, ry1) = tris.pos_rel(rx, ry, RelVertPos::Right)?;
-                (rx2, ry2) = tris.pos_rel(rx, ry, RelVertPos::DownRight)?;
-            }
-            let verts = (tri, *tris.get_vert(rx1, ry1), *tris.get_vert(rx2, ry2));
-            let colors = average(&get_color_in_triangle(
-                image,
-                Triangle(verts.0, verts.1, verts.2),
-            ));
-            Some(
-                Polygon::new()
-                    .set(
-                        "fill",
-                        format!("rgb({}, {}, {})", colors.0[0], colors.0[1], colors.0[2]),
-                    )
-                    .set(
-                        "stroke",
-                        if tracing_mode { "rgb(255, 0, 0)".into() } else { format!("rgb({}, {}, {})", colors.0[0], colors.0[1], colors.0[2]) },
-                    )
-                    .set(
-                        "points",
-                        format!(
-                            "{},{} {},{} {},{}",
-                            verts.0.x, verts.0.y, verts.1.x, verts.1.y, verts.2.x, verts.2.y
-                        ),
+        .triangles()
+        .into_iter()
+        .map(|verts| {
+            let colors = stats.color(verts).unwrap_or(Rgb([0; 3]));
+            Polygon::new()
+                .set(
+                    "fill",
+                    format!("rgb({}, {}, {})", colors.0[0], colors.0[1], colors.0[2]),
+                )
+                .set(
+                    "stroke",
+                    if tracing_mode { "rgb(255, 0, 0)".into() } else { format!("rgb({}, {}, {})", colors.0[0], colors.0[1], colors.0[2]) },
+                )
+                .set(
+                    "points",
+                    format!(
+                        "{},{} {},{} {},{}",
+                        verts.0.x, verts.0.y, verts.1.x, verts.1.y, verts.2.x, verts.2.y
                     ),
-            )
+                )
         });
     let mut doc = Document::new().set("viewBox", (0, 0, image_size, image_size));
     for node in nodes {
-        if let Some(node) = node {
-            doc = doc.add(node);
-        }
+        doc = doc.add(node);
     }
     doc.to_string()
 }
 
+/// loads a grayscale weight map, and scales it to the size of the (already scaled) image it is for,
+/// the same way [`scale_image`] scaled the image
+pub fn load_weight_map(file: PathBuf, width: u32, height: u32) -> anyhow::Result<GrayImage> {
+    let map = image::open(&file)
+        .with_context(|| format!("failed to load weight map {file:?}"))?
+        .to_luma8();
+    let (aspect, map_aspect) = (width as f64 / height as f64, map.width() as f64 / map.height() as f64);
+    if (aspect - map_aspect).abs() > 0.01 * aspect {
+        warn!("weight map is {}x{}, which is not the same aspect ratio as the image, it will be stretched to fit", map.width(), map.height());
+    }
+    Ok(image::imageops::resize(&map, width, height, image::imageops::Lanczos3))
+}
+
 pub fn load_image(file: PathBuf) -> RgbImage {
     let path = file.canonicalize().expect("invalid path!");
     assert!(path.exists(), "input file must exist!");
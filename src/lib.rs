@@ -0,0 +1,30 @@
+//! trifit: image compression and art generation with triangles!
+//!
+//! the main entry point is [`fit`], which takes an image and a [`FitConfig`] and gives back
+//! the optimized grid of [`Triangles`] (or [`fit_mesh`], which can also give back an adaptively refined [`TriMesh`]). for more control over the process (progress reporting,
+//! stopping part way through, ect) use a [`Fitter`] directly.
+
+#[macro_use]
+extern crate log;
+
+pub mod checkpoint;
+pub mod colors;
+pub mod convergence;
+pub mod fit;
+pub mod io;
+pub mod mesh;
+pub mod mesh_format;
+pub mod metrics;
+pub mod optimizer;
+pub mod scoring;
+pub mod triangle;
+pub mod vec2;
+
+pub use checkpoint::Checkpoint;
+pub use convergence::{Convergence, StopReason};
+pub use fit::{best_move, fit, fit_mesh, optimize_one, FitConfig, Fitter, Level, RunObserver, StepReport};
+pub use io::OutputFormat;
+pub use mesh::{Mesh, TriMesh};
+pub use optimizer::{Optimizer, OptimizerKind};
+pub use scoring::{ColorSpace, SamplingMode, Score, Scorer, ScoringScheme};
+pub use triangle::Triangles;
@@ -1,26 +1,20 @@
 #[macro_use]
 extern crate log;
 
-pub mod colors;
-pub mod io;
-pub mod scoring;
-pub mod triangle;
-pub mod vec2;
-
 use std::{
     fs::OpenOptions,
     io::{Read, Seek, SeekFrom},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         atomic::{self, AtomicBool},
         Arc,
     },
     thread::{self, JoinHandle},
-    time::Instant,
+    time::Duration,
 };
 
-use anyhow::Result;
-use clap::{ArgGroup, Parser, ValueEnum};
+use anyhow::{Context, Result};
+use clap::{ArgGroup, Parser};
 use glutin_window::GlutinWindow;
 use image::{codecs::gif::GifEncoder, DynamicImage, Rgb, RgbImage, RgbaImage};
 use opengl_graphics::{GlGraphics, OpenGL, Texture, TextureSettings};
@@ -29,16 +23,28 @@ use piston::{
     window::WindowSettings,
     RenderEvent, Size, UpdateEvent,
 };
+use serde::{de::DeserializeOwned, Serialize};
 use stati::prelude::*;
 
-use colors::*;
-use io::{load_image, save, scale_image};
-use rand::{prelude::SliceRandom, Rng, SeedableRng};
-use scoring::{
-    average, get_color_in_triangle, point_in_triangle, rectangle_by_points, score, score_for_group
+use trifit::{
+    colors::*,
+    convergence::StopReason,
+    io::{self, load_image, load_weight_map, save, scale_image},
+    mesh::{FlipCriterion, Initializer, Mesh, TriMesh},
+    mesh_format::{self, MeshFile},
+    metrics::Quality,
+    optimizer::{Anneal, Cooling, Gradient, Greedy, Growth, Optimizer, OptimizerKind, SearchPattern, Shifts},
+    scoring::{
+        composite::{Composite, TermWeight},
+        gradient::GradientMap,
+        rectangle_by_points, score,
+        stats::ImageStats,
+        ColorSpace, Scorer,
+    },
+    vec2::F64x2,
+    Checkpoint, Convergence, FitConfig, Fitter, Level, OutputFormat, RunObserver, SamplingMode, ScoringScheme, StepReport,
+    Triangles,
 };
-use triangle::Triangles;
-use vec2::F64x2;
 
 #[derive(Debug, Clone, Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -70,6 +76,52 @@ pub struct Args {
     )]
     randomness: usize,
 
+    #[clap(
+        long,
+        arg_enum,
+        value_parser,
+        help = "how to move the verticies. `greedy` always takes the best move, `anneal` sometimes takes worse ones \
+                (less and less often as it goes on) to get out of local optima, and `gradient` moves them the way \
+                the score gets better fastest (checking far fewer moves)",
+        default_value = "greedy"
+    )]
+    optimizer: OptimizerKind,
+
+    #[clap(
+        long,
+        help = "starting temperature for `--optimizer anneal`. a move that makes the score this much worse \
+                (as a fraction of the score) is taken about a third of the time",
+        default_value = "0.02"
+    )]
+    temperature: f64,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_parser,
+        help = "how the temperature goes down for `--optimizer anneal`. `exponential` multiplies it by --cooling-rate \
+                every iteration, and `linear` takes it to zero at the last iteration",
+        default_value = "exponential"
+    )]
+    cooling: Cooling,
+
+    #[clap(long, help = "what the temperature is multiplied by every iteration with `--cooling exponential`", default_value = "0.9")]
+    cooling_rate: f64,
+
+    #[clap(
+        long,
+        help = "how many times `--optimizer gradient` can halve its step (which starts at --steps times --shift) looking for a better position",
+        default_value = "5"
+    )]
+    halvings: u32,
+
+    #[clap(
+        long,
+        help = "how many times `--optimizer gradient` can double its step while the position keeps getting better",
+        default_value = "3"
+    )]
+    doublings: u32,
+
     #[clap(help = "file to output to")]
     output: Option<PathBuf>,
 
@@ -83,6 +135,27 @@ pub struct Args {
     )]
     exit_early: bool,
 
+    #[clap(long, help = "stop once an iteration moves no more than this fraction of the verticies (eg: 0.01)")]
+    min_moved: Option<f64>,
+
+    #[clap(
+        long,
+        help = "stop once the score improves by less than this fraction over the last --improvement-window iterations (eg: 0.001)"
+    )]
+    min_improvement: Option<f64>,
+
+    #[clap(long, help = "number of iterations --min-improvement looks back over", default_value = "5")]
+    improvement_window: usize,
+
+    #[clap(long, help = "stop after this many seconds (checked after each iteration)")]
+    time_limit: Option<f64>,
+
+    #[clap(long, help = "stop once the PSNR of the result is at least this many dB")]
+    target_psnr: Option<f64>,
+
+    #[clap(long, help = "stop once the SSIM of the result is at least this (0-1)")]
+    target_ssim: Option<f64>,
+
     #[clap(long, short, arg_enum, value_parser, help = "output format to use")]
     format: Option<OutputFormat>,
 
@@ -95,25 +168,229 @@ pub struct Args {
     )]
     scoring: ScoringScheme,
 
+    #[clap(
+        long = "term",
+        value_name = "TERM=WEIGHT",
+        help = "weight of a term for `--scoring composite` (can be given multiple times, eg: `--term min-angle=0.5`). \
+                terms: color-variance, percentile-deviation, squareness, min-angle, area-deviation, edge-alignment"
+    )]
+    terms: Vec<TermWeight>,
+
+    #[clap(long, help = "JSON file with the weights for `--scoring composite` (any `--term`s are applied on top of it)")]
+    scoring_config: Option<PathBuf>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_parser,
+        help = "how pixels on the edges of triangles are sampled. `coverage` weights each pixel by how much of it the triangle covers",
+        default_value = "center"
+    )]
+    sampling: SamplingMode,
+
+    #[clap(
+        long,
+        help = "grayscale image (the same aspect ratio as the input) of how much each part of the image matters. \
+                the optimizer spends more effort where it is bright"
+    )]
+    weight_map: Option<PathBuf>,
+
+    #[clap(long, action, help = "average the colors of triangles in linear light, rather than in (gamma encoded) sRGB")]
+    linear_fill: bool,
+
     #[clap(long, action, help="draw lines on the edges of triangles to aid in tracing")]
     tracing_mode: bool,
-}
 
-#[derive(Debug, Clone, ValueEnum)]
-pub enum OutputFormat {
-    Svg,
-    Image,
-    Mindustry,
-    /// temporary mode for input/output GIF rendering. does not support display mode. input file must be a gif
-    GifThroughput,
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "number of steps (of size `shift`) to try in each direction",
+        default_value = "4"
+    )]
+    steps: u32,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "number of directions to step in, spread evenly around a square (8 is the sides and diagonals, 16 adds the ones in between)",
+        default_value = "8"
+    )]
+    directions: u32,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_parser,
+        help = "how far apart the steps in each direction are. `linear` is 1, 2, 3... shifts out, and `geometric` is 1, 2, 4...",
+        default_value = "linear"
+    )]
+    growth: Growth,
+
+    #[clap(long, help = "number of extra moves to random places (within the furthest step) to try", default_value = "0")]
+    random_moves: u32,
+
+    #[clap(
+        long,
+        action,
+        help = "halve the shift of a vertex every time it can not find a better position, and double it back when it does"
+    )]
+    adaptive_shift: bool,
+
+    #[clap(long, help = "file to periodically save progress to, so the run can be resumed with --resume")]
+    checkpoint: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "number of iterations between each checkpoint",
+        default_value = "10"
+    )]
+    checkpoint_every: usize,
+
+    #[clap(long, help = "checkpoint file to resume a previous run from")]
+    resume: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "load an already optimized mesh (saved with `--format mesh`) instead of optimizing, and save it straight to the output",
+        requires = "out"
+    )]
+    mesh_input: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "refine the mesh as it is fit (splitting triangles where there is detail, and merging them where there is not), \
+                using at most this many triangles"
+    )]
+    max_triangles: Option<usize>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_parser,
+        help = "flip edges after every iteration when it makes the triangles better, to clean up slivers. \
+                `delaunay` keeps the triangles as fat as possible, and `score` flips when it scores better"
+    )]
+    edge_flips: Option<FlipCriterion>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_parser,
+        help = "how to lay out the starting mesh. `jitter` moves the verticies of the grid randomly, `poisson` places them \
+                randomly but closer together along edges in the image, and `corners` puts them on corners in the image",
+        default_value = "grid"
+    )]
+    init: Initializer,
+
+    #[clap(
+        long,
+        action,
+        help = "let the verticies on the edges of the image slide along the edge, instead of keeping them where they start"
+    )]
+    slide_border: bool,
+
+    #[clap(long, help = "seed for the random number generator, runs with the same seed and arguments give identical results")]
+    seed: Option<u64>,
+
+    #[clap(
+        long,
+        help = "number of threads to optimize with (0 uses all cores)",
+        default_value = "1"
+    )]
+    threads: usize,
+
+    #[clap(
+        long,
+        help = "fit the mesh to smaller copies of the image first (each half the size of the last), splitting the \
+                iterations evenly between them. 1 fits the full size image the whole time",
+        default_value = "1"
+    )]
+    pyramid_levels: usize,
 }
-#[derive(Debug, Clone, Copy, ValueEnum, Default)]
-pub enum ScoringScheme {
-    /// percentile based system that is weighted against small triangles
-    #[default]
-    PercentileWithSizeWeight,
-    /// average based, weighted against very thin triangles
-    AvgWithShapeWeight,
+
+impl Args {
+    fn scorer(&self) -> Result<Arc<dyn Scorer>> {
+        if !matches!(self.scoring, ScoringScheme::Composite) {
+            if !self.terms.is_empty() || self.scoring_config.is_some() {
+                warn!("--term and --scoring-config only do anything with `--scoring composite`");
+            }
+            return Ok(self.scoring.scorer());
+        }
+        let mut composite = match &self.scoring_config {
+            Some(path) => Composite::load(path)?,
+            None if self.terms.is_empty() => Composite::default(),
+            None => Composite::empty(),
+        };
+        for &TermWeight(term, weight) in &self.terms {
+            composite = composite.weight(term, weight);
+        }
+        debug!("Scoring with {composite:?}");
+        Ok(Arc::new(composite))
+    }
+
+    fn optimizer(&self) -> Arc<dyn Optimizer> {
+        match self.optimizer {
+            OptimizerKind::Greedy => Arc::new(Greedy),
+            OptimizerKind::Anneal => Arc::new(
+                Anneal::new(self.temperature)
+                    .cooling(self.cooling)
+                    .cooling_rate(self.cooling_rate),
+            ),
+            OptimizerKind::Gradient => Arc::new(
+                Gradient::default()
+                    .halvings(self.halvings)
+                    .doublings(self.doublings),
+            ),
+        }
+    }
+
+    fn fit_config(&self) -> Result<FitConfig> {
+        let mut cfg = FitConfig::default()
+            .image_size(self.image_size)
+            .tri_size(self.tri_size)
+            .shift(self.shift)
+            .iterations(self.iterations)
+            .randomness(self.randomness)
+            .sampling(self.sampling)
+            .steps(self.steps)
+            .search(
+                SearchPattern::default()
+                    .directions(self.directions)
+                    .growth(self.growth)
+                    .random(self.random_moves)
+                    .adaptive(self.adaptive_shift),
+            )
+            .exit_early(self.exit_early)
+            .convergence(
+                Convergence::default()
+                    .min_moved(self.min_moved)
+                    .min_improvement(self.min_improvement, self.improvement_window)
+                    .time_limit(self.time_limit.map(Duration::from_secs_f64))
+                    .target_psnr(self.target_psnr)
+                    .target_ssim(self.target_ssim),
+            )
+            .seed(self.seed)
+            .threads(self.threads)
+            .max_triangles(self.max_triangles)
+            .edge_flips(self.edge_flips)
+            .initializer(self.init)
+            .slide_border(self.slide_border)
+            .pyramid_levels(self.pyramid_levels);
+        cfg.scorer = self.scorer()?;
+        cfg.optimizer = self.optimizer();
+        Ok(cfg)
+    }
+
+    /// if the mesh has to be able to change shape, rather than being the regular grid
+    fn needs_trimesh(&self) -> bool {
+        self.max_triangles.is_some() || self.edge_flips.is_some() || self.init != Initializer::Grid
+    }
+
+    /// stats used to pick the color each triangle is filled with
+    fn fill_stats(&self, image: &RgbImage) -> ImageStats {
+        let space = if self.linear_fill { ColorSpace::LinearRgb } else { ColorSpace::Srgb };
+        ImageStats::in_space(image, space).sampling(self.sampling)
+    }
 }
 
 #[tokio::main]
@@ -168,103 +445,19 @@ async fn main() -> Result<()> {
         for (frame_num, (frame, raw_frame)) in input_frames.into_iter().enumerate() {
             info!("Frame {frame_num}/{total_frames}");
             // scale the image to the size specified in the args, while retainging the aspect ratio
-            let (w, h, raw_image, _padded_image) = scale_image(frame, args.image_size);
-
-            let mut recvd_tris = Triangles::new(
-                w + (args.tri_size - w as f64 % args.tri_size.ceil()) as u32,
-                h + (args.tri_size - h as f64 % args.tri_size.ceil()) as u32,
-                args.tri_size,
-            );
+            let (_, _, raw_image, _padded_image) = scale_image(frame, args.image_size);
 
-            // communication between the processing and display threads
-            let proc_thread_comm = flume::bounded::<(usize, Triangles)>(2);
-            let proc_thread_kill = Arc::new(AtomicBool::new(false));
-            let proc_thread_kill2 = proc_thread_kill.clone();
-
-            // copy of inputs for proc thread
-            let raw_image2 = raw_image.clone();
-            let args2 = args.clone();
-            let mut proc_thread = Some(thread::spawn(move || {
-                let image = raw_image2;
-                let args = args2;
-                let mut tris = Triangles::new(
-                    w + (args.tri_size - w as f64 % args.tri_size.ceil()) as u32,
-                    h + (args.tri_size - h as f64 % args.tri_size.ceil()) as u32,
-                    args.tri_size,
-                );
-                let mut last_tris = tris.clone();
-                // counts the number of steps left
-                let mut iteration: usize = 0;
-                let mut rng = rand::rngs::StdRng::from_entropy();
-
-                'main: loop {
-                    if iteration >= args.iterations {
-                        break;
-                    }
-                    let starttime = Instant::now();
-
-                    // randomly iterate through the verticies of the grid
-                    let mut verts = tris.clone().into_iter_verts().collect::<Vec<_>>();
-                    verts.shuffle(&mut rng);
-                    for (x, y, _) in verts {
-                        // for each vertex, run a optimization on it that shifts it to the best nearby position, if there is one.
-                        optimize_one(&image, &mut tris, (x, y), &args);
-                        if proc_thread_kill2.load(atomic::Ordering::Relaxed) {
-                            break 'main;
-                        }
-                    }
-                    iteration += 1;
-                    let endtime = Instant::now();
-                    let opt_dur = endtime - starttime;
-                    // report back to the display thread with progress to be shown
-                    proc_thread_comm
-                        .0
-                        .send((iteration, tris.clone()))
-                        .expect("Processing thread exiting -- main thread panic detected");
-                    println!("Optimizer step");
-                    println!("    iteration #{iteration}");
-                    println!("    took {opt_dur:?}");
-                    if args.exit_early {
-                        if tris == last_tris {
-                            println!("No more work to do, finishing early");
-                            proc_thread_comm
-                                .0
-                                .send((usize::MAX /* signals that all iterations are complete, even if they are not */, tris.clone()))
-                                .expect("Processing thread exiting -- main thread panic detected");
-                            break;
-                        } else {
-                            last_tris = tris.clone();
-                        }
-                    }
-                }
-            }));
-
-            loop {
-                match proc_thread_comm.1.recv() {
-                    Ok(values) => {
-                        recvd_tris = values.1;
-                    }
-                    Err(flume::RecvError::Disconnected) => {
-                        if let Some(proc_thread) = proc_thread.take() {
-                            info!("Done processing frame");
-                            match proc_thread.join() {
-                                Ok(..) => {}
-                                Err(err) => std::panic::panic_any(err),
-                            }
-                            rendered_frames.push((
-                                io::render_image(&recvd_tris, &raw_image, args.image_size, args.tracing_mode),
-                                raw_frame,
-                            ))
-                        }
-                        break;
-                    }
-                }
-            }
+            let rendered = match args.needs_trimesh() {
+                true => fit_frame::<TriMesh>(&args, &raw_image)?,
+                false => fit_frame::<Triangles>(&args, &raw_image)?,
+            };
+            rendered_frames.push((rendered, raw_frame));
         }
         info!("Saving gif");
         let output_file = OpenOptions::new()
             .create(true)
             .write(true)
+            .truncate(true)
             .open(args.output.as_ref().unwrap())?;
         let mut encoder = GifEncoder::new(output_file);
         encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
@@ -281,6 +474,137 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if let Some(mesh_path) = &args.mesh_input {
+        info!("Loading mesh from {mesh_path:?}");
+        let (w, h, raw_image, _) = scale_image(load_image(args.file.clone()), args.image_size);
+        let fill_stats = args.fill_stats(&raw_image);
+        let (output, format) = (args.output.clone().unwrap(), args.format.clone().unwrap());
+        // the mesh has to have been fit to the image at the same size, or it will not line up with it
+        let mismatch = || format!("mesh {mesh_path:?} was not fit to a {w}x{h} image (--image-size {})", args.image_size);
+        if mesh_format::is_trimesh(mesh_path)? {
+            let mesh = mesh_format::load::<TriMesh>(mesh_path)?;
+            mesh.check_made_for(w, h, mesh.size_of_chunk()).with_context(mismatch)?;
+            save(&mesh, &fill_stats, args.image_size, output, format, args.tracing_mode);
+        } else {
+            let tris = mesh_format::load::<Triangles>(mesh_path)?;
+            tris.check_made_for(w, h, tris.size_of_chunk()).with_context(mismatch)?;
+            save(&tris, &fill_stats, args.image_size, output, format, args.tracing_mode);
+        }
+        return Ok(());
+    }
+
+    match args.needs_trimesh() {
+        true => run::<TriMesh>(args),
+        false => run::<Triangles>(args),
+    }
+}
+
+/// reports on how the fit is going from the processing thread, and sends the mesh to the display thread after
+/// every iteration
+struct Reporter<'r, M> {
+    comm: &'r flume::Sender<(usize, M)>,
+    kill: &'r AtomicBool,
+    /// the full size image, to measure the quality of the mesh against
+    image: &'r RgbImage,
+    fill_stats: &'r ImageStats,
+    checkpoint_path: Option<&'r Path>,
+    checkpoint_every: usize,
+    max_triangles: bool,
+    edge_flips: bool,
+    /// progress bar for the iteration that is running
+    bar: Option<(stati::BarManager<'static>, stati::wrapper::BarWrapper<stati::bars::SimpleBar>)>,
+}
+
+impl<M: Mesh + Serialize + DeserializeOwned> Reporter<'_, M> {
+    fn save_checkpoint(&self, checkpoint: &Checkpoint<M>) {
+        if let Some(path) = self.checkpoint_path {
+            match checkpoint.save(path) {
+                Ok(()) => info!("Saved checkpoint to {path:?}"),
+                Err(err) => error!("Failed to save checkpoint: {err:#}"),
+            }
+        }
+    }
+}
+
+impl<M: Mesh + Serialize + DeserializeOwned> RunObserver<M> for Reporter<'_, M> {
+    fn progress(&mut self, done: usize, total: usize) -> bool {
+        let (bman, bar) = self.bar.get_or_insert_with(|| {
+            let mut bman = stati::BarManager::new();
+            let bar = bman.register(stati::bars::SimpleBar::new("Iteration progress", total));
+            (bman, bar)
+        });
+        bar.bar().set_progress(done);
+        bman.print();
+        !self.kill.load(atomic::Ordering::Relaxed)
+    }
+
+    fn step(&mut self, level: Level, report: &StepReport, checkpoint: &Checkpoint<M>) {
+        self.bar = None;
+        let iteration = report.iteration;
+        // report back to the display thread with progress to be shown
+        self.comm
+            .send((iteration, checkpoint.tris.clone()))
+            .expect("Processing thread exiting -- main thread panic detected");
+        println!("Optimizer step");
+        println!("    iteration #{iteration}");
+        if level.level != 0 {
+            println!("    at {}x{}", level.size.0, level.size.1);
+        }
+        println!("    took {:?}", report.duration);
+        if self.max_triangles {
+            println!(
+                "    split {}, collapsed {}, now {} triangles",
+                report.refinement.split,
+                report.refinement.collapsed,
+                checkpoint.tris.triangles().len()
+            );
+        }
+        if self.edge_flips {
+            println!("    flipped {} edges", report.flipped);
+        }
+        let quality = Quality::measure(&checkpoint.tris, self.fill_stats, self.image);
+        println!("    PSNR {:.2}dB, SSIM {:.4}", quality.psnr, quality.ssim);
+        if iteration.is_multiple_of(self.checkpoint_every) {
+            self.save_checkpoint(checkpoint);
+        }
+    }
+
+    fn level_done(&mut self, level: Level, reason: StopReason, checkpoint: &Checkpoint<M>) {
+        self.bar = None;
+        if matches!(reason, StopReason::Interrupted) {
+            return;
+        }
+        // checkpoints are always of the full size mesh, so the smaller levels are saved too
+        self.save_checkpoint(checkpoint);
+        if matches!(reason, StopReason::Finished) {
+            return;
+        }
+        if level.level != 0 && reason.converged() {
+            println!("Done at this size ({reason}), moving on");
+            return;
+        }
+        let tris = checkpoint.tris.clone();
+        self.comm
+            .send((usize::MAX /* signals that all iterations are complete, even if they are not */, tris))
+            .expect("Processing thread exiting -- main thread panic detected");
+    }
+}
+
+/// fits one frame of a gif, and renders it
+fn fit_frame<M: Mesh>(args: &Args, image: &RgbImage) -> Result<RgbaImage> {
+    let mut fitter = Fitter::<M>::for_image(image, args.fit_config()?);
+    if let Some(path) = &args.weight_map {
+        fitter = fitter.with_weight_map(&load_weight_map(path.clone(), image.width(), image.height())?);
+    }
+    fitter.run();
+    Ok(io::render_image(fitter.triangles(), &args.fill_stats(image), args.image_size, args.tracing_mode))
+}
+
+/// fits the mesh to the image (showing progress, unless visuals are turned off), and then saves it
+fn run<M>(args: Args) -> Result<()>
+where
+    M: Mesh + MeshFile + Serialize + DeserializeOwned + 'static,
+{
     let (
         raw_image,
         padded_image,
@@ -290,7 +614,10 @@ async fn main() -> Result<()> {
         proc_thread_comm,
         proc_thread_kill,
         mut proc_thread,
-    ) = run_for_image(args.clone());
+        (scorer, score_stats),
+    ) = run_for_image::<M>(args.clone())?;
+
+    let fill_stats = args.fill_stats(&raw_image);
 
     if !args.no_visuals {
         // Change this to OpenGL::V2_1 if not working.
@@ -341,50 +668,45 @@ async fn main() -> Result<()> {
                             gl,
                         );
 
-                    for (x, y, _) in recvd_tris.clone().into_iter_verts() {
-                        recvd_tris
-                            .triangles_around_point(x, y)
-                            .into_iter()
-                            .for_each(|mut t| {
-                                let score = score(t, &raw_image, args.tri_size, args.scoring).score_value();
-                                t = t.offset(40.0, 40.0);
-                                t = t.offset(
-                                    (args.image_size - w) as f64 / 2.0,
-                                    (args.image_size - h) as f64 / 2.0,
-                                );
-                                if recvd_iteration < args.iterations {
-                                    // let color = rgba(
-                                    //     if score <= 255.0 { score as u8 } else { 0 },
-                                    //     if score <= 255.0 * 2.0 && score > 255.0 {
-                                    //         (score - 255.0) as u8
-                                    //     } else {
-                                    //         0
-                                    //     },
-                                    //     if score <= 255.0 * 3.0 && score > 255.0 * 2.0 {
-                                    //         (score - 255.0 * 2.0) as u8
-                                    //     } else {
-                                    //         0
-                                    //     },
-                                    //     1.0,
-                                    // );
-
-                                    // let color = BLUE;
-
-                                    let color = rgba((score * 25.5).clamp(0.0, 255.0) as u8, 0, 0, 1.0);
-                                    t.draw_outline(2.0, color, &c, gl);
-                                } else {
-                                    let color;
-                                    let colors = get_color_in_triangle(&raw_image, t);
-                                    if colors.is_empty() {
-                                        color = rgba(0, 0, 0, 0.0);
-                                    } else {
-                                        let Rgb([r, g, b]) = average(&colors);
-                                        color = rgba(r, g, b, 1.0);
-                                    }
-                                    t.draw(color, &c, gl);
-                                }
-                            });
-                    }
+                    recvd_tris
+                        .triangles()
+                        .into_iter()
+                        .for_each(|mut t| {
+                            let score = scorer.display_value(&score(t, &score_stats, args.tri_size, &*scorer));
+                            let fill = fill_stats.color(t);
+                            t = t.offset(40.0, 40.0);
+                            t = t.offset(
+                                (args.image_size - w) as f64 / 2.0,
+                                (args.image_size - h) as f64 / 2.0,
+                            );
+                            if recvd_iteration < args.iterations {
+                                // let color = rgba(
+                                //     if score <= 255.0 { score as u8 } else { 0 },
+                                //     if score <= 255.0 * 2.0 && score > 255.0 {
+                                //         (score - 255.0) as u8
+                                //     } else {
+                                //         0
+                                //     },
+                                //     if score <= 255.0 * 3.0 && score > 255.0 * 2.0 {
+                                //         (score - 255.0 * 2.0) as u8
+                                //     } else {
+                                //         0
+                                //     },
+                                //     1.0,
+                                // );
+
+                                // let color = BLUE;
+
+                                let color = rgba((score * 25.5).clamp(0.0, 255.0) as u8, 0, 0, 1.0);
+                                t.draw_outline(2.0, color, &c, gl);
+                            } else {
+                                let color = match fill {
+                                    Some(Rgb([r, g, b])) => rgba(r, g, b, 1.0),
+                                    None => rgba(0, 0, 0, 0.0),
+                                };
+                                t.draw(color, &c, gl);
+                            }
+                        });
                 });
             }
 
@@ -406,7 +728,7 @@ async fn main() -> Result<()> {
                             if args.output.is_some() {
                                 save(
                                     &recvd_tris,
-                                    &raw_image,
+                                    &fill_stats,
                                     args.image_size,
                                     args.output.clone().unwrap(),
                                     args.format.clone().unwrap(),
@@ -425,8 +747,9 @@ async fn main() -> Result<()> {
         // run untill computation is done, and then save the image
         loop {
             match proc_thread_comm.recv() {
-                Ok(values) => {
-                    let _ = values;
+                Ok((_, tris)) => {
+                    // the iteration is only used to draw the mesh, so there is no need to keep it here
+                    recvd_tris = tris;
                 }
                 Err(flume::RecvError::Disconnected) => {
                     if let Some(proc_thread) = proc_thread.take() {
@@ -438,7 +761,7 @@ async fn main() -> Result<()> {
                         if args.output.is_some() {
                             save(
                                 &recvd_tris,
-                                &raw_image,
+                                &fill_stats,
                                 args.image_size,
                                 args.output.clone().unwrap(),
                                 args.format.clone().unwrap(),
@@ -468,94 +791,120 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn run_for_image(
+#[allow(clippy::type_complexity)]
+fn run_for_image<M>(
     args: Args,
-) -> (
+) -> Result<(
     RgbImage,
     RgbImage,
     (u32, u32),
-    Triangles,
+    M,
     usize,
-    flume::Receiver<(usize, Triangles)>,
+    flume::Receiver<(usize, M)>,
     Arc<AtomicBool>,
     Option<JoinHandle<()>>,
-) {
+    (Arc<dyn Scorer>, ImageStats),
+)>
+where
+    M: Mesh + Serialize + DeserializeOwned + 'static,
+{
     let unscaled = load_image(args.file.clone());
     // scale the image to the size specified in the args, while retainging the aspect ratio
     let (w, h, raw_image, padded_image) = scale_image(unscaled, args.image_size);
 
-    // create the starting grid of triangles
-    let original_tris = Triangles::new(
-        w + (args.tri_size - w as f64 % args.tri_size.ceil()) as u32,
-        h + (args.tri_size - h as f64 % args.tri_size.ceil()) as u32,
-        args.tri_size,
-    );
+    // create the starting grid of triangles, or pick up where a previous run left off
+    let mut cfg = args.fit_config()?;
+    let checkpoint = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::<M>::load(path)?;
+            info!("Resuming from {path:?} at iteration {}", checkpoint.iteration);
+            checkpoint
+                .tris
+                .check_made_for(w, h, args.tri_size)
+                .with_context(|| format!("checkpoint {path:?} does not match the given arguments"))?;
+            Some(checkpoint)
+        }
+        None => None,
+    };
+    // the seed is picked here so the starting mesh can be laid out (and shown) before the fitter is created.
+    // a resumed run carries on with the seed it was started with
+    let seed = match &checkpoint {
+        Some(checkpoint) => {
+            if cfg.seed.is_some_and(|seed| seed != checkpoint.seed) {
+                warn!("ignoring the given seed, since the checkpoint was made with the seed {}", checkpoint.seed);
+            }
+            checkpoint.seed
+        }
+        None => cfg.seed.unwrap_or_else(rand::random),
+    };
+    cfg.seed = Some(seed);
     // variables to be filled in by the processing thread
-    let recvd_tris = original_tris.clone();
-    let recvd_iteration = 0usize;
+    let recvd_tris = match &checkpoint {
+        Some(checkpoint) => checkpoint.tris.clone(),
+        None => M::initial(&raw_image, &cfg, seed),
+    };
+    let recvd_iteration = checkpoint.as_ref().map_or(0, |c| c.iteration);
 
     // communication between the processing and display threads
-    let proc_thread_comm = flume::bounded::<(usize, Triangles)>(2);
+    let proc_thread_comm = flume::bounded::<(usize, M)>(2);
     let proc_thread_kill = Arc::new(AtomicBool::new(false));
     let proc_thread_kill2 = proc_thread_kill.clone();
 
     // copy of inputs for proc thread
     let raw_image2 = raw_image.clone();
-    let args2 = args.clone();
+    let fill_stats = args.fill_stats(&raw_image);
+    let initial_tris = recvd_tris.clone();
+    // the gradient is only worked out once, and shared between the fitter and the display
+    let gradient = cfg
+        .scorer
+        .needs_gradient()
+        .then(|| Arc::new(GradientMap::new(&raw_image)));
+    let mut score_stats = ImageStats::in_space(&raw_image, cfg.scorer.color_space()).sampling(args.sampling);
+    if let Some(gradient) = &gradient {
+        score_stats.set_gradient(gradient.clone());
+    }
+    let weight_map = match &args.weight_map {
+        Some(path) => Some(load_weight_map(path.clone(), w, h)?),
+        None => None,
+    };
+    if let Some(map) = &weight_map {
+        score_stats.set_weight_map(map);
+    }
+    let scorer = cfg.scorer.clone();
+    let checkpoint_path = args.checkpoint.clone();
+    let checkpoint_every = args.checkpoint_every.max(1);
     let proc_thread = Some(thread::spawn(move || {
         let image = raw_image2;
-        let args = args2;
-        let mut tris = original_tris;
-        let mut last_tris = tris.clone();
-        // counts the number of steps left
-        let mut iteration: usize = 0;
-
-        'main: loop {
-            if iteration >= args.iterations {
-                break;
-            }
-            let starttime = Instant::now();
-
-            // randomly iterate through the verticies of the grid
-            let mut verts = tris.clone().into_iter_verts().collect::<Vec<_>>();
-            verts.shuffle(&mut rand::thread_rng());
-            let len = verts.len();
-            let mut bman = stati::BarManager::new();
-            for (x, y, _) in verts.into_iter().display_bar(bman.register(stati::bars::SimpleBar::new("Iteration progress", len))) {
-                bman.print();
-                // for each vertex, run a optimization on it that shifts it to the best nearby position, if there is one.
-                optimize_one(&image, &mut tris, (x, y), &args);
-                if proc_thread_kill2.load(atomic::Ordering::Relaxed) {
-                    break 'main;
-                }
-            }
-            iteration += 1;
-            let endtime = Instant::now();
-            let opt_dur = endtime - starttime;
-            // report back to the display thread with progress to be shown
-            proc_thread_comm
-                .0
-                .send((iteration, tris.clone()))
-                .expect("Processing thread exiting -- main thread panic detected");
-            println!("Optimizer step");
-            println!("    iteration #{iteration}");
-            println!("    took {opt_dur:?}");
-            if args.exit_early {
-                if tris == last_tris {
-                    println!("No more work to do, finishing early");
-                    proc_thread_comm
-                        .0
-                        .send((usize::MAX /* signals that all iterations are complete, even if they are not */, tris.clone()))
-                        .expect("Processing thread exiting -- main thread panic detected");
-                    break;
-                } else {
-                    last_tris = tris.clone();
-                }
-            }
+        info!("Using seed {seed}");
+        let start = checkpoint.unwrap_or(Checkpoint {
+            iteration: 0,
+            seed,
+            tris: initial_tris,
+            shifts: Shifts::default(),
+        });
+        let mut fitter = Fitter::resume(&image, cfg, start);
+        if let Some(gradient) = gradient {
+            fitter = fitter.with_gradient(gradient);
+        }
+        if let Some(map) = &weight_map {
+            fitter = fitter.with_weight_map(map);
         }
+        let mut reporter = Reporter {
+            comm: &proc_thread_comm.0,
+            kill: &proc_thread_kill2,
+            image: &image,
+            fill_stats: &fill_stats,
+            checkpoint_path: checkpoint_path.as_deref(),
+            checkpoint_every,
+            max_triangles: fitter.config().max_triangles.is_some(),
+            edge_flips: fitter.config().edge_flips.is_some(),
+            bar: None,
+        };
+        let reason = fitter.run_with(&mut reporter);
+        println!("Stopped: {reason}");
     }));
 
-    (
+    Ok((
         raw_image,
         padded_image,
         (w, h),
@@ -564,88 +913,6 @@ fn run_for_image(
         proc_thread_comm.1,
         proc_thread_kill,
         proc_thread,
-    )
-}
-
-/// finds a new optimal position for a vertex in the grid of triangles
-pub fn optimize_one(image: &RgbImage, tris: &mut Triangles, xy: (u32, u32), args: &Args) {
-    let shift_amnt = args.shift;
-    let randomness = args.randomness;
-    // do not move edge verts
-    if tris.vert_is_edge(xy.0, xy.1) {
-        return;
-    }
-    // get the triangles around the current point
-    let group = tris.triangles_around_point(xy.0, xy.1);
-    // and score for that group
-    let original_score = score_for_group(image, &group, args.tri_size, args.scoring);
-
-    // possible movements of the point (all directions, and up to some number of steps in that direction)
-    let perms = [
-        (0.0, 1.0), //   up
-        // (0.5, 1.0),
-        (1.0, 1.0), //   up right
-        // (1.0, 0.5),
-        (1.0, 0.0), //   right
-        // (1.0, -0.5),
-        (1.0, -1.0), //  down right
-        // (0.5, -1.0),
-        (0.0, -1.0), //  down
-        // (-0.5, -1.0),
-        (-1.0, -1.0), // down left
-        // (-1.0, -0.5),
-        (-1.0, 0.0), //  left
-        // (-1.0, 0.5),
-        (-1.0, 1.0), //  up left
-                     // (-0.5, 1.0),
-    ]
-    .into_iter()
-    .map(|(x, y)| (x * shift_amnt, y * shift_amnt))
-    .map(|(x, y)| {
-        (1..=4/* number of steps in each direction */) // TODO: make this configurable
-            .map(|i| (x * i as f64, y * i as f64))
-            .collect::<Vec<_>>()
-    })
-    .flatten();
-
-    let tris2 = tris.clone();
-
-    let scores = perms
-        .filter(|(dx, dy)| {
-            let new = *tris2.get_vert(xy.0, xy.1) + F64x2::new(*dx, *dy);
-            tris2
-                .triangles_around_point(xy.0, xy.1)
-                .into_iter()
-                .map(|t| point_in_triangle(new, t.0, t.1, t.2))
-                .any(|x| x)
-        })
-        .map(|(dx, dy)| {
-            let original = *tris.get_vert(xy.0, xy.1);
-            let at = tris.get_vert_mut(xy.0, xy.1);
-            at.x += dx;
-            at.y += dy;
-            let group = tris.triangles_around_point(xy.0, xy.1);
-            let new_score = score_for_group(image, &group, args.tri_size, args.scoring);
-            // println!("    possible new score: {new_score}");
-            *tris.get_vert_mut(xy.0, xy.1) = original;
-            (dx, dy, new_score)
-        })
-        .collect::<Vec<_>>();
-    let best = scores.iter().max_by(|(_, _, a), (_, _, b)| a.cmp(b)); // larger scores are considered better
-
-    if let Some((mut dx, mut dy, mut best_score)) = best.cloned()
-    {
-        if randomness != 0 {
-            if rand::thread_rng().gen_bool(1.0 / randomness as f64) {
-                (dx, dy, best_score) = scores.choose(&mut rand::thread_rng()).unwrap().clone();
-            }
-        }
-        if best_score.cmp(&original_score).is_gt()
-        {
-            // println!("yay");
-            let at = tris.get_vert_mut(xy.0, xy.1);
-            at.x += dx;
-            at.y += dy;
-        }
-    }
+        (scorer, score_stats),
+    ))
 }
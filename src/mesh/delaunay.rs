@@ -0,0 +1,197 @@
+//! delaunay triangulation of a set of points (with the Bowyer-Watson algorithm).
+//!
+//! the triangulation starts as the rectangle around the points, cut in two. points are then added one at a time,
+//! left to right. each one removes the triangles whose circumcircle it is inside, and fills the hole with
+//! triangles fanning out from the new point. since the points come in order, a triangle whose circumcircle is
+//! entirely to the left of the current point can never be removed again, so only the triangles along the sweep
+//! line have to be checked
+
+use std::collections::HashSet;
+
+use crate::{triangle::Triangle, vec2::F64x2};
+
+/// a triangle that might still be removed, along with its circumcircle
+#[derive(Debug, Clone, Copy)]
+struct Open {
+    corners: [usize; 3],
+    center: F64x2,
+    radius_sq: f64,
+}
+
+impl Open {
+    fn new(corners: [usize; 3], points: &[F64x2]) -> Self {
+        let [a, b, c] = corners.map(|v| points[v]);
+        let (b, c) = (b - a, c - a);
+        let d = 2.0 * b.cross_2v(c);
+        let offset = F64x2::new(
+            c.y * b.dot(b) - b.y * c.dot(c),
+            b.x * c.dot(c) - c.x * b.dot(b),
+        ) / d;
+        Self {
+            corners,
+            center: a + offset,
+            radius_sq: offset.dot(offset),
+        }
+    }
+
+    fn circumcircle_contains(&self, p: F64x2) -> bool {
+        let d = p - self.center;
+        d.dot(d) < self.radius_sq
+    }
+}
+
+/// triangulates `points`, giving the corners of each triangle (in the same winding order as
+/// [`Triangle::signed_area`] uses).
+///
+/// the points have to include the four corners of the rectangle around them, and every point should be distinct.
+/// the result covers that rectangle exactly, with points along its sides kept as corners of the triangles
+/// next to them. starting from the rectangle (rather than a huge triangle around everything) keeps points along
+/// the sides from making long thin triangles that the circumcircle test can not handle accurately
+pub fn triangulate(points: &[F64x2]) -> Vec<[usize; 3]> {
+    let (min, max) = points.iter().fold(
+        (F64x2::new(f64::INFINITY, f64::INFINITY), F64x2::new(f64::NEG_INFINITY, f64::NEG_INFINITY)),
+        |(min, max), p| (F64x2::new(min.x.min(p.x), min.y.min(p.y)), F64x2::new(max.x.max(p.x), max.y.max(p.y))),
+    );
+    if !(min.x < max.x && min.y < max.y) {
+        return vec![];
+    }
+    let corner = |x: f64, y: f64| {
+        points
+            .iter()
+            .position(|p| *p == F64x2::new(x, y))
+            .expect("the corners of the rectangle around the points have to be included")
+    };
+    let [top_left, top_right, bottom_right, bottom_left] =
+        [(min.x, min.y), (max.x, min.y), (max.x, max.y), (min.x, max.y)].map(|(x, y)| corner(x, y));
+
+    let mut order = (0..points.len())
+        .filter(|p| ![top_left, top_right, bottom_right, bottom_left].contains(p))
+        .collect::<Vec<_>>();
+    order.sort_by(|a, b| points[*a].x.total_cmp(&points[*b].x).then(points[*a].y.total_cmp(&points[*b].y)));
+
+    let mut open = vec![
+        Open::new([top_left, top_right, bottom_right], points),
+        Open::new([top_left, bottom_right, bottom_left], points),
+    ];
+    let mut done = vec![];
+    for p in order {
+        let pos = points[p];
+        let mut bad = vec![];
+        let mut i = 0;
+        while i < open.len() {
+            let tri = open[i];
+            let right = tri.center.x + tri.radius_sq.sqrt();
+            if right < pos.x {
+                done.push(tri.corners);
+                open.swap_remove(i);
+            } else if tri.circumcircle_contains(pos) {
+                bad.push(tri.corners);
+                open.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        // the sides of the hole left by the removed triangles, going the same way around as the triangles did
+        // (kept in order, so the result is always the same)
+        let sides = bad
+            .iter()
+            .flat_map(|[a, b, c]| [(*a, *b), (*b, *c), (*c, *a)])
+            .collect::<Vec<_>>();
+        let inner = sides.iter().copied().collect::<HashSet<_>>();
+        for &(a, b) in &sides {
+            // a point on a side of the rectangle would make a triangle with no area out of that side
+            if !inner.contains(&(b, a)) && signed_area([a, b, p], points) > 0.0 {
+                open.push(Open::new([a, b, p], points));
+            }
+        }
+    }
+    done.extend(open.iter().map(|tri| tri.corners));
+    done
+}
+
+fn signed_area([a, b, c]: [usize; 3], points: &[F64x2]) -> f64 {
+    Triangle(points[a], points[b], points[c]).signed_area()
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// checks that `faces` cover the rectangle from the origin to `size` exactly: every face is wound the right way
+    /// and inside the rectangle, no face is there twice, their areas add up to the rectangle, and every side is
+    /// either shared with exactly one other face (going the other way) or on the outside of the rectangle
+    pub(in crate::mesh) fn assert_covers_rect(points: &[F64x2], faces: &[[usize; 3]], size: F64x2) {
+        let mut unique = HashSet::new();
+        let mut sides = HashSet::new();
+        let mut area = 0.0;
+        for (f, &[a, b, c]) in faces.iter().enumerate() {
+            let tri = Triangle(points[a], points[b], points[c]);
+            assert!(tri.signed_area() > 0.0, "face {f} is inverted or has no area");
+            for p in [tri.0, tri.1, tri.2] {
+                assert!(p.x >= 0.0 && p.y >= 0.0 && p.x <= size.x && p.y <= size.y, "face {f} is outside the rectangle");
+            }
+            let mut sorted = [a, b, c];
+            sorted.sort();
+            assert!(unique.insert(sorted), "face {f} is there more than once");
+            for side in [(a, b), (b, c), (c, a)] {
+                assert!(sides.insert(side), "two faces go the same way along {side:?}, so they overlap");
+            }
+            area += tri.signed_area();
+        }
+        let expected = size.x * size.y;
+        assert!((area - expected).abs() < 1e-6 * expected, "faces cover an area of {area}, not {expected}");
+        for &(a, b) in &sides {
+            let (p, q) = (points[a], points[b]);
+            let on_outside = (p.x == q.x && (p.x == 0.0 || p.x == size.x)) || (p.y == q.y && (p.y == 0.0 || p.y == size.y));
+            assert!(sides.contains(&(b, a)) || on_outside, "side {:?} is only used once, but is not on the outside", (a, b));
+        }
+    }
+
+    /// the corners of a `width` by `height` rectangle, and `n` points along its sides
+    fn rect(width: f64, height: f64, n: usize) -> Vec<F64x2> {
+        let mut points = vec![
+            F64x2::new(0.0, 0.0),
+            F64x2::new(width, 0.0),
+            F64x2::new(width, height),
+            F64x2::new(0.0, height),
+        ];
+        for i in 1..n {
+            let t = i as f64 / n as f64;
+            points.extend([
+                F64x2::new(t * width, 0.0),
+                F64x2::new(t * width, height),
+                F64x2::new(0.0, t * height),
+                F64x2::new(width, t * height),
+            ]);
+        }
+        points
+    }
+
+    #[test]
+    fn covers_rectangle() {
+        let (width, height) = (50.0, 30.0);
+        let mut points = rect(width, height, 5);
+        // scattered points inside, some of them lined up with each other and with the ones on the sides
+        for i in 0..60 {
+            let x = ((i * 37) % 47) as f64 + 1.5;
+            let y = ((i * 11) % 27) as f64 + 1.5;
+            points.push(F64x2::new(x, y));
+        }
+        points.push(F64x2::new(20.0, 15.0));
+        points.push(F64x2::new(30.0, 15.0));
+        let faces = triangulate(&points);
+        assert_covers_rect(&points, &faces, F64x2::new(width, height));
+        // every point is used
+        let used = faces.iter().flatten().copied().collect::<HashSet<_>>();
+        assert_eq!(used.len(), points.len());
+    }
+
+    #[test]
+    fn covers_rectangle_with_only_a_border() {
+        let points = rect(40.0, 40.0, 4);
+        let faces = triangulate(&points);
+        assert_covers_rect(&points, &faces, F64x2::new(40.0, 40.0));
+    }
+}
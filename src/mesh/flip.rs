@@ -0,0 +1,152 @@
+//! edge flips, which swap the edge between two triangles for the other diagonal of the shape they make.
+//!
+//! as verticies move around they tend to leave long thin slivers behind, which the scorers only weakly
+//! discourage. flipping the edge between a sliver and its neighbour usually fixes it, without adding any verticies
+
+use std::f64::consts::PI;
+
+use clap::ValueEnum;
+
+use super::TriMesh;
+use crate::{
+    fit::FitConfig,
+    scoring::{score_for_group, stats::ImageStats},
+    triangle::Triangle,
+    vec2::F64x2,
+};
+
+/// how far (in radians) past a straight line the angles across from an edge have to add up to for it to be
+/// flipped, so points that are (almost) on a circle do not flip back and forth
+const DELAUNAY_EPSILON: f64 = 1e-9;
+
+/// when an edge should be flipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FlipCriterion {
+    /// when it makes the mesh closer to a delaunay triangulation, which has the fattest triangles possible.
+    /// this is when the two angles across from the edge add up to more than 180 degrees
+    Delaunay,
+    /// when the two new triangles score better (with [`FitConfig::scorer`]) than the two old ones
+    Score,
+}
+
+/// goes over every edge inside the mesh once, flipping the ones that `criterion` says should be.
+/// returns how many were flipped
+pub fn flip_edges(mesh: &mut TriMesh, stats: &ImageStats, cfg: &FitConfig, criterion: FlipCriterion) -> usize {
+    let mut flipped = 0;
+    for (a, b) in mesh.edges().collect::<Vec<_>>() {
+        // the edge might have already been flipped away
+        let Some([a, b, c, d]) = mesh.quad(a, b) else {
+            continue;
+        };
+        let [pa, pb, pc, pd] = [a, b, c, d].map(|v| mesh.positions()[v]);
+        let better = match criterion {
+            FlipCriterion::Delaunay => angle(pc, pa, pb) + angle(pd, pb, pa) > PI + DELAUNAY_EPSILON,
+            FlipCriterion::Score => {
+                let scorer = &*cfg.scorer;
+                let value = |group: &[Triangle]| scorer.value(&score_for_group(stats, group, cfg.tri_size, scorer));
+                value(&[Triangle(pa, pd, pc), Triangle(pd, pb, pc)]) > value(&[Triangle(pa, pb, pc), Triangle(pb, pa, pd)])
+            }
+        };
+        if better && mesh.flip_edge(a, b) {
+            flipped += 1;
+        }
+    }
+    flipped
+}
+
+/// the angle at `at` between the lines to `a` and `b`
+fn angle(at: F64x2, a: F64x2, b: F64x2) -> f64 {
+    let (u, v) = (a - at, b - at);
+    u.cross_2v(v).abs().atan2(u.dot(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        mesh::{tri_mesh::tests::assert_valid, Mesh},
+        triangle::Triangles,
+    };
+
+    /// the square from (0, 0) to (20, 20), split along the diagonal from `a` to the corner across from it
+    fn square(a: usize) -> TriMesh {
+        let verts = [(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)].map(|(x, y)| F64x2::new(x, y));
+        let (b, c, d) = ((a + 1) % 4, (a + 2) % 4, (a + 3) % 4);
+        TriMesh::from_parts(verts.to_vec(), vec![true; 4], vec![[a, b, c], [a, c, d]], 20.0)
+    }
+
+    /// the diagonal of a mesh made of two triangles
+    fn diagonal(mesh: &TriMesh) -> HashSet<usize> {
+        let [f, g] = [0, 1].map(|f| mesh.faces()[f].into_iter().collect::<HashSet<_>>());
+        f.intersection(&g).copied().collect()
+    }
+
+    fn edges_outside(mesh: &TriMesh) -> HashSet<(usize, usize)> {
+        (0..mesh.face_count() * 3)
+            .filter(|h| mesh.twin(*h).is_none())
+            .map(|h| (mesh.origin(h), mesh.dest(h)))
+            .collect()
+    }
+
+    fn cfg() -> FitConfig {
+        FitConfig {
+            tri_size: 20.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delaunay_flips_thin_triangles_once() {
+        // a flat diamond, split along its long diagonal
+        let verts = [(0.0, 0.0), (10.0, 1.0), (20.0, 0.0), (10.0, -1.0)].map(|(x, y)| F64x2::new(x, y));
+        let mut mesh = TriMesh::from_parts(verts.to_vec(), vec![true; 4], vec![[0, 2, 1], [2, 0, 3]], 20.0);
+        let stats = ImageStats::from_fn(20, 20, |_, _| [0.0; 3]);
+        assert_eq!(flip_edges(&mut mesh, &stats, &cfg(), FlipCriterion::Delaunay), 1);
+        assert_eq!(diagonal(&mesh), HashSet::from([1, 3]));
+        assert_valid(&mesh);
+        // and the short diagonal is already as good as it gets
+        assert_eq!(flip_edges(&mut mesh, &stats, &cfg(), FlipCriterion::Delaunay), 0);
+    }
+
+    #[test]
+    fn score_only_flips_when_it_is_better() {
+        let stats = ImageStats::from_fn(20, 20, |x, y| if x + y < 20 { [0.0; 3] } else { [255.0; 3] });
+        let mut mesh = square(0);
+        assert_eq!(flip_edges(&mut mesh, &stats, &cfg(), FlipCriterion::Score), 1);
+        assert_eq!(diagonal(&mesh), HashSet::from([1, 3]));
+        assert_valid(&mesh);
+        assert_eq!(flip_edges(&mut mesh, &stats, &cfg(), FlipCriterion::Score), 0);
+        assert_eq!(flip_edges(&mut square(1), &stats, &cfg(), FlipCriterion::Score), 0);
+
+        // on a flat image neither diagonal is any better
+        let flat = ImageStats::from_fn(20, 20, |_, _| [100.0; 3]);
+        for a in [0, 1] {
+            assert_eq!(flip_edges(&mut square(a), &flat, &cfg(), FlipCriterion::Score), 0);
+        }
+    }
+
+    #[test]
+    fn border_is_never_flipped() {
+        let stats = ImageStats::from_fn(60, 40, |x, y| [(x * 4) as f64, (y * 6) as f64, ((x * y) % 255) as f64]);
+        let cfg = FitConfig {
+            tri_size: 10.0,
+            ..Default::default()
+        };
+        for criterion in [FlipCriterion::Delaunay, FlipCriterion::Score] {
+            let mut mesh = TriMesh::from(&Triangles::new(60, 40, 10.0));
+            // push every other row (which are a triangle apart) over to one side, so there are plenty of edges worth flipping
+            for v in 0..mesh.vert_count() {
+                let p = mesh.positions()[v];
+                if !mesh.on_border()[v] && (p.y / cfg.tri_size).round() as i64 % 2 == 1 {
+                    mesh.set_vert(v, p + F64x2::new(8.0, 0.0));
+                }
+            }
+            let outside = edges_outside(&mesh);
+            assert!(flip_edges(&mut mesh, &stats, &cfg, criterion) > 0, "{criterion:?}");
+            assert_eq!(edges_outside(&mesh), outside, "{criterion:?}");
+            assert_valid(&mesh);
+        }
+    }
+}
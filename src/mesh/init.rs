@@ -0,0 +1,358 @@
+//! where the verticies of a new mesh start out.
+//!
+//! the regular grid is the default, but then the first dozens of iterations are spent just dragging verticies
+//! over to the edges in the image. the other initializers place verticies where the detail is (or at least less
+//! regularly) and connect them up with a [`delaunay`](super::delaunay) triangulation, so the optimizer starts
+//! from a mesh that already roughly follows the image.
+//!
+//! apart from the grid, every initializer puts verticies along the border of the image (about a triangle
+//! apart), so the mesh covers the image exactly
+
+use std::f64::consts::TAU;
+
+use clap::ValueEnum;
+use image::RgbImage;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{delaunay, TriMesh};
+use crate::{fit::FitConfig, scoring::gradient::GradientMap, triangle::Triangles, vec2::F64x2};
+
+/// how far jittered verticies can move from where they are in the grid (in x and y), as a fraction of the spacing
+const JITTER: f64 = 0.35;
+/// how many times more verticies there are per area where the edges in the image are as strong as they are on
+/// average, compared to where it is flat
+const GRADIENT_DENSITY: f64 = 3.0;
+/// poisson disk radius (as a fraction of the triangle size) that gives about as many verticies as the grid
+const POISSON_SPACING: f64 = 0.85;
+/// smallest and largest poisson disk radius, as a fraction of the triangle size
+const RADIUS_RANGE: (f64, f64) = (0.3, 3.0);
+/// number of tries to place a new point near each existing one, before moving on (see [`poisson`])
+const CANDIDATES: usize = 30;
+/// size of the area around each pixel looked at when finding corners (this many pixels each way)
+const CORNER_WINDOW: usize = 2;
+/// weakest corner kept, as a fraction of the strongest one in the image
+const CORNER_THRESHOLD: f64 = 0.01;
+/// most verticies that can be placed on corners, as a fraction of how many the grid would have
+const CORNER_FRACTION: f64 = 0.25;
+/// mixed into the seed, so the initializer does not use the same random numbers as the first iteration
+const SEED_KEY: u64 = 0xD1B5_4A32_D192_ED03;
+
+/// how the verticies of a new mesh are placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Initializer {
+    /// the regular grid of triangles
+    #[default]
+    Grid,
+    /// a regular grid with every vertex moved a random amount
+    Jitter,
+    /// randomly placed verticies (poisson disk sampled) that are packed closer together where the image has
+    /// stronger edges
+    Poisson,
+    /// verticies on the corners found in the image, with evenly spaced ones filling in everywhere else
+    Corners,
+}
+
+/// the starting mesh for an image, as picked by [`FitConfig::initializer`] (and randomized with `seed`)
+pub fn initial_mesh(image: &RgbImage, cfg: &FitConfig, seed: u64) -> TriMesh {
+    let (width, height) = image.dimensions();
+    let size = cfg.tri_size;
+    if cfg.initializer == Initializer::Grid {
+        return TriMesh::from(Triangles::for_image(width, height, size));
+    }
+    let mut rng = StdRng::seed_from_u64(seed ^ SEED_KEY);
+    let mut points = border(width, height, size);
+    let num_border = points.len();
+    match cfg.initializer {
+        Initializer::Grid => unreachable!(),
+        Initializer::Jitter => points.extend(jittered_grid(width, height, size, &mut rng)),
+        Initializer::Poisson => {
+            let density = edge_density(&GradientMap::new(image), size);
+            let mean = 1.0 + GRADIENT_DENSITY;
+            let radius = |p: F64x2| radius_for_density(size, density(p) / mean);
+            points = poisson(points, width, height, radius, size * RADIUS_RANGE.1, &mut rng);
+        }
+        Initializer::Corners => {
+            let grid_verts = width as f64 * height as f64 / (size * size);
+            points.extend(corners(&GradientMap::new(image), size, (grid_verts * CORNER_FRACTION) as usize));
+            points = poisson(points, width, height, |_| size * POISSON_SPACING, size, &mut rng);
+        }
+    }
+    let faces = delaunay::triangulate(&points);
+    let on_border = (0..points.len()).map(|v| v < num_border).collect();
+    TriMesh::from_parts(points, on_border, faces, size)
+}
+
+/// points along the edges of the image (including the corners), about `size` apart
+fn border(width: u32, height: u32, size: f64) -> Vec<F64x2> {
+    let (width, height) = (width as f64, height as f64);
+    let (cols, rows) = (segments(width, size), segments(height, size));
+    let (dx, dy) = (width / cols as f64, height / rows as f64);
+    let mut points = vec![];
+    for i in 0..cols {
+        points.push(F64x2::new(i as f64 * dx, 0.0));
+        points.push(F64x2::new(width - i as f64 * dx, height));
+    }
+    for i in 0..rows {
+        points.push(F64x2::new(width, i as f64 * dy));
+        points.push(F64x2::new(0.0, height - i as f64 * dy));
+    }
+    points
+}
+
+/// how many pieces of about `size` to split a length into
+fn segments(len: f64, size: f64) -> usize {
+    ((len / size).round() as usize).max(1)
+}
+
+/// the inside of a grid like the one [`border`] makes the edges of (with every other row offset by half a
+/// triangle), with each vertex moved randomly
+fn jittered_grid(width: u32, height: u32, size: f64, rng: &mut StdRng) -> Vec<F64x2> {
+    let (width, height) = (width as f64, height as f64);
+    let (cols, rows) = (segments(width, size), segments(height, size));
+    let (dx, dy) = (width / cols as f64, height / rows as f64);
+    let mut points = vec![];
+    for y in 1..rows {
+        let offset = if y % 2 == 1 { 0.5 } else { 0.0 };
+        for x in 0..=cols {
+            let x = (x as f64 + offset) * dx;
+            if x <= 0.0 || x >= width {
+                continue;
+            }
+            let jitter = F64x2::new(rng.gen_range(-JITTER..=JITTER) * dx, rng.gen_range(-JITTER..=JITTER) * dy);
+            points.push(F64x2::new(x, y as f64 * dy) + jitter);
+        }
+    }
+    points
+}
+
+/// poisson disk radius for a place that should have `density` times as many points as average
+fn radius_for_density(size: f64, density: f64) -> f64 {
+    let (min, max) = RADIUS_RANGE;
+    (size * POISSON_SPACING / density.sqrt()).clamp(size * min, size * max)
+}
+
+/// how many points there should be around each part of the image (1 where it is flat, and
+/// `1 + GRADIENT_DENSITY` where the edges are as strong as average), blurred over about a triangle
+fn edge_density(gradient: &GradientMap, size: f64) -> impl Fn(F64x2) -> f64 {
+    let (width, height) = (gradient.width() as usize, gradient.height() as usize);
+    let strength = pixels(width, height, |p| gradient.at(p).length());
+    let blurred = box_blur(&strength, width, height, (size / 2.0) as usize);
+    let mean = blurred.iter().sum::<f64>() / blurred.len() as f64;
+    move |p: F64x2| {
+        let x = (p.x.max(0.0) as usize).min(width - 1);
+        let y = (p.y.max(0.0) as usize).min(height - 1);
+        match mean > 0.0 {
+            true => 1.0 + GRADIENT_DENSITY * blurred[y * width + x] / mean,
+            // completely flat, so spread them out evenly
+            false => 1.0 + GRADIENT_DENSITY,
+        }
+    }
+}
+
+/// the strongest corners in the image (by the Shi-Tomasi measure, the smaller eigenvalue of the structure tensor),
+/// at least `size / 2` apart and away from the edges of the image
+fn corners(gradient: &GradientMap, size: f64, max: usize) -> Vec<F64x2> {
+    let (width, height) = (gradient.width() as usize, gradient.height() as usize);
+    let blur = |f: &dyn Fn(F64x2) -> f64| box_blur(&pixels(width, height, f), width, height, CORNER_WINDOW);
+    let xx = blur(&|p| gradient.at(p).x * gradient.at(p).x);
+    let yy = blur(&|p| gradient.at(p).y * gradient.at(p).y);
+    let xy = blur(&|p| gradient.at(p).x * gradient.at(p).y);
+    let response = (0..width * height)
+        .map(|i| {
+            let (a, b, c) = (xx[i], xy[i], yy[i]);
+            (a + c) / 2.0 - (((a - c) / 2.0).powi(2) + b * b).sqrt()
+        })
+        .collect::<Vec<_>>();
+    let strongest = response.iter().copied().fold(0.0, f64::max);
+    if strongest <= 0.0 {
+        return vec![];
+    }
+
+    let mut candidates = (0..width * height)
+        .filter(|i| response[*i] > strongest * CORNER_THRESHOLD)
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| response[*b].total_cmp(&response[*a]));
+    let spacing = size / 2.0;
+    let mut corners: Vec<F64x2> = vec![];
+    let mut near = PointGrid::new(width as u32, height as u32, spacing);
+    for i in candidates {
+        if corners.len() >= max {
+            break;
+        }
+        let p = F64x2::new((i % width) as f64 + 0.5, (i / width) as f64 + 0.5);
+        let inside = p.x > spacing && p.y > spacing && p.x < width as f64 - spacing && p.y < height as f64 - spacing;
+        if inside && near.within(p, spacing).all(|q| (corners[q] - p).length() >= spacing) {
+            near.insert(corners.len(), p);
+            corners.push(p);
+        }
+    }
+    corners
+}
+
+/// adds points to the image (poisson disk sampling, with Bridson's algorithm) untill there is no room for more.
+///
+/// no two points are closer together than the average of their `radius`, which can be at most `max_radius`.
+/// the points already there are kept, and new points are placed around them
+fn poisson(
+    mut points: Vec<F64x2>,
+    width: u32,
+    height: u32,
+    radius: impl Fn(F64x2) -> f64,
+    max_radius: f64,
+    rng: &mut StdRng,
+) -> Vec<F64x2> {
+    let mut radii = points.iter().map(|p| radius(*p)).collect::<Vec<_>>();
+    let mut near = PointGrid::new(width, height, max_radius / 2.0);
+    for (i, p) in points.iter().enumerate() {
+        near.insert(i, *p);
+    }
+    let fits = |p: F64x2, r: f64, points: &[F64x2], radii: &[f64], near: &PointGrid| {
+        // not too close to the border either, which would make slivers against it
+        let inside = p.x > r / 2.0 && p.y > r / 2.0 && p.x < width as f64 - r / 2.0 && p.y < height as f64 - r / 2.0;
+        inside
+            && near
+                .within(p, max_radius)
+                .all(|q| (points[q] - p).length() >= (r + radii[q]) / 2.0)
+    };
+
+    let mut active = (0..points.len()).collect::<Vec<_>>();
+    while !active.is_empty() {
+        let i = rng.gen_range(0..active.len());
+        let (p, r) = (points[active[i]], radii[active[i]]);
+        let found = (0..CANDIDATES).find_map(|_| {
+            let angle = rng.gen_range(0.0..TAU);
+            let dist = rng.gen_range(r..2.0 * r);
+            let candidate = p + F64x2::new(angle.cos(), angle.sin()) * dist;
+            let r = radius(candidate);
+            fits(candidate, r, &points, &radii, &near).then_some((candidate, r))
+        });
+        match found {
+            Some((p, r)) => {
+                near.insert(points.len(), p);
+                active.push(points.len());
+                points.push(p);
+                radii.push(r);
+            }
+            None => {
+                active.swap_remove(i);
+            }
+        }
+    }
+    points
+}
+
+/// `f` at the center of every pixel, row by row
+fn pixels(width: usize, height: usize, f: impl Fn(F64x2) -> f64) -> Vec<f64> {
+    (0..width * height)
+        .map(|i| f(F64x2::new((i % width) as f64 + 0.5, (i / width) as f64 + 0.5)))
+        .collect()
+}
+
+/// the average of the values within `radius` pixels (in x and y) of each pixel
+fn box_blur(values: &[f64], width: usize, height: usize, radius: usize) -> Vec<f64> {
+    // sums of everything above and to the left, with an extra row and column of zeros
+    let mut sums = vec![0.0; (width + 1) * (height + 1)];
+    for y in 0..height {
+        let mut row = 0.0;
+        for x in 0..width {
+            row += values[y * width + x];
+            sums[(y + 1) * (width + 1) + x + 1] = sums[y * (width + 1) + x + 1] + row;
+        }
+    }
+    let sum = |x: usize, y: usize| sums[y * (width + 1) + x];
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+            let (x1, y1) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+            let total = sum(x1, y1) - sum(x0, y1) - sum(x1, y0) + sum(x0, y0);
+            total / ((x1 - x0) * (y1 - y0)) as f64
+        })
+        .collect()
+}
+
+/// buckets of points, for finding the ones near a position quickly
+struct PointGrid {
+    cell: f64,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl PointGrid {
+    fn new(width: u32, height: u32, cell: f64) -> Self {
+        let cols = (width as f64 / cell) as usize + 1;
+        let rows = (height as f64 / cell) as usize + 1;
+        Self {
+            cell,
+            cols,
+            rows,
+            cells: vec![vec![]; cols * rows],
+        }
+    }
+
+    fn cell_of(&self, p: F64x2) -> (usize, usize) {
+        let x = ((p.x / self.cell).max(0.0) as usize).min(self.cols - 1);
+        let y = ((p.y / self.cell).max(0.0) as usize).min(self.rows - 1);
+        (x, y)
+    }
+
+    fn insert(&mut self, i: usize, p: F64x2) {
+        let (x, y) = self.cell_of(p);
+        self.cells[y * self.cols + x].push(i);
+    }
+
+    /// every point that might be within `dist` of `p` (and some that are not)
+    fn within(&self, p: F64x2, dist: f64) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = self.cell_of(p);
+        let reach = (dist / self.cell).ceil() as usize;
+        let (x0, x1) = (x.saturating_sub(reach), (x + reach).min(self.cols - 1));
+        let (y0, y1) = (y.saturating_sub(reach), (y + reach).min(self.rows - 1));
+        (y0..=y1).flat_map(move |y| (x0..=x1).flat_map(move |x| self.cells[y * self.cols + x].iter().copied()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+    use crate::mesh::delaunay::tests::assert_covers_rect;
+
+    /// a dark image with a bright rectangle in it, so there are edges and corners to find
+    fn image() -> RgbImage {
+        RgbImage::from_fn(90, 60, |x, y| match (25..60).contains(&x) && (15..45).contains(&y) {
+            true => Rgb([230, 200, 40]),
+            false => Rgb([20, 30, 60]),
+        })
+    }
+
+    fn assert_valid(initializer: Initializer) {
+        let image = image();
+        let (width, height) = (image.width() as f64, image.height() as f64);
+        let cfg = FitConfig::default().tri_size(10.0).initializer(initializer);
+        for seed in 0..3 {
+            let mesh = initial_mesh(&image, &cfg, seed);
+            assert_covers_rect(mesh.positions(), mesh.faces(), F64x2::new(width, height));
+            for (v, p) in mesh.positions().iter().enumerate() {
+                let on_border = p.x == 0.0 || p.y == 0.0 || p.x == width || p.y == height;
+                assert_eq!(mesh.on_border()[v], on_border, "{initializer:?}: vertex {v} at {p:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_covers_image() {
+        assert_valid(Initializer::Jitter);
+    }
+
+    #[test]
+    fn poisson_covers_image() {
+        assert_valid(Initializer::Poisson);
+    }
+
+    #[test]
+    fn corners_covers_image() {
+        assert_valid(Initializer::Corners);
+    }
+}
@@ -0,0 +1,228 @@
+//! the shapes of mesh that can be fit to an image.
+//!
+//! the optimizer, exporters and metrics work on anything implementing [`Mesh`]. this is either the regular
+//! grid ([`Triangles`]), or a [`TriMesh`] (a half-edge mesh), which can have any topology and so can be refined as it
+//! is fit (see [`refine`] and [`flip`]). any grid can be turned into a [`TriMesh`] with the exact same triangles
+
+use std::fmt::Debug;
+
+use anyhow::{bail, Result};
+use image::RgbImage;
+
+use crate::{
+    fit::FitConfig,
+    scoring::stats::ImageStats,
+    triangle::{Triangle, Triangles},
+    vec2::F64x2,
+};
+
+pub mod delaunay;
+pub mod flip;
+pub mod init;
+pub mod refine;
+pub mod tri_mesh;
+
+pub use flip::FlipCriterion;
+pub use init::Initializer;
+pub use refine::Refinement;
+pub use tri_mesh::TriMesh;
+
+/// how a vertex is allowed to move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Movement {
+    /// anywhere
+    Free,
+    /// only along the side of the mesh it is on, which goes in this direction (a unit vector)
+    Along(F64x2),
+    /// not at all, since it is on a corner of the mesh
+    Fixed,
+}
+
+/// a mesh of triangles that can be fit to an image
+pub trait Mesh: Debug + Clone + Send + Sync {
+    /// identifies a vertex of the mesh
+    type Vert: Debug + Copy + Eq + Send + Sync;
+
+    /// the mesh to start fitting `image` from, placed by [`FitConfig::initializer`] (using `seed` for anything random)
+    fn initial(image: &RgbImage, cfg: &FitConfig, seed: u64) -> Self;
+
+    /// every vertex, always in the same order
+    fn verts(&self) -> Vec<Self::Vert>;
+
+    fn vert(&self, v: Self::Vert) -> F64x2;
+
+    fn set_vert(&mut self, v: Self::Vert, pos: F64x2);
+
+    /// how the vertex is allowed to move
+    fn movement(&self, v: Self::Vert) -> Movement;
+
+    /// if verticies must not be moved anywhere that turns one of their triangles inside out (see
+    /// [`MoveInfo::keep_winding`](crate::optimizer::MoveInfo::keep_winding))
+    fn keeps_winding(&self) -> bool {
+        true
+    }
+
+    /// a number unique to each vertex, so each one can get its own RNG
+    fn vert_key(&self, v: Self::Vert) -> u64;
+
+    /// the triangles with `v` as a corner (always as their first corner), along with the index of each one
+    fn faces_around(&self, v: Self::Vert) -> Vec<(usize, Triangle)>;
+
+    /// verticies that share an edge with `v`
+    fn neighbours(&self, v: Self::Vert) -> Vec<Self::Vert>;
+
+    /// every edge, each exactly once, as the verticies at either end
+    fn edges(&self) -> Vec<(Self::Vert, Self::Vert)> {
+        self.verts()
+            .into_iter()
+            .flat_map(|v| {
+                self.neighbours(v)
+                    .into_iter()
+                    .filter(move |n| self.vert_key(*n) > self.vert_key(v))
+                    .map(move |n| (v, n))
+            })
+            .collect()
+    }
+
+    /// upper bound on the indices given by [`Mesh::faces_around`]
+    fn face_index_bound(&self) -> usize;
+
+    /// every triangle in the mesh, each exactly once
+    fn triangles(&self) -> Vec<Triangle>;
+
+    /// splits all of the verticies into sets where no two verticies in the same set are neighbours
+    /// (see [`Triangles::independent_sets`])
+    fn independent_sets(&self) -> Vec<Vec<Self::Vert>>;
+
+    /// size of the triangles the mesh was created with
+    fn size_of_chunk(&self) -> f64;
+
+    /// checks that this mesh could have been made for an image of `width` by `height` with triangles of `tri_size`
+    /// (eg: so a checkpoint is not resumed with different settings), with an error saying what does not match.
+    ///
+    /// by default only the size of the triangles is checked
+    fn check_made_for(&self, _width: u32, _height: u32, tri_size: f64) -> Result<()> {
+        check_tri_size(self, tri_size)
+    }
+
+    /// moves every vertex as if the whole mesh was scaled by `factor` (around the origin), to fit it to a
+    /// larger or smaller copy of the image. this only changes where the verticies are, not anything else
+    /// about the mesh (like [`Mesh::size_of_chunk`])
+    fn scale(&mut self, factor: f64) {
+        for v in self.verts() {
+            self.set_vert(v, self.vert(v) * factor);
+        }
+    }
+
+    /// changes the topology of the mesh to fit the image better, using at most `max_triangles` triangles.
+    ///
+    /// the grid can not change shape, so by default this does nothing
+    fn refine(&mut self, _stats: &ImageStats, _cfg: &FitConfig, _max_triangles: usize) -> Refinement {
+        Refinement::default()
+    }
+
+    /// flips the edges that `criterion` says should be (see [`flip`]), returning how many were.
+    ///
+    /// like [`Mesh::refine`], this does nothing for the grid
+    fn flip_edges(&mut self, _stats: &ImageStats, _cfg: &FitConfig, _criterion: FlipCriterion) -> usize {
+        0
+    }
+}
+
+fn check_tri_size(mesh: &impl Mesh, tri_size: f64) -> Result<()> {
+    if mesh.size_of_chunk() != tri_size {
+        bail!("the mesh was made with a tri size of {}, but {tri_size} was given", mesh.size_of_chunk());
+    }
+    Ok(())
+}
+
+impl Mesh for Triangles {
+    type Vert = (u32, u32);
+
+    /// always the regular grid, since that is the only shape it can be
+    fn initial(image: &RgbImage, cfg: &FitConfig, _seed: u64) -> Self {
+        Triangles::for_image(image.width(), image.height(), cfg.tri_size)
+    }
+
+    fn verts(&self) -> Vec<(u32, u32)> {
+        self.clone().into_iter_verts().map(|(x, y, _)| (x, y)).collect()
+    }
+
+    fn vert(&self, (x, y): (u32, u32)) -> F64x2 {
+        *self.get_vert(x, y)
+    }
+
+    fn set_vert(&mut self, (x, y): (u32, u32), pos: F64x2) {
+        *self.get_vert_mut(x, y) = pos;
+    }
+
+    /// the top and bottom rows slide sideways, and the ends of the odd rows slide up and down. the even rows stick
+    /// out half a triangle past the odd ones on either side, so moving their ends up or down would fold over the
+    /// triangles next to them, and they stay put
+    fn movement(&self, (x, y): (u32, u32)) -> Movement {
+        match (self.vert_is_top_or_bottom(y), self.vert_is_side(x, y)) {
+            (false, false) => Movement::Free,
+            (true, false) => Movement::Along(F64x2::new(1.0, 0.0)),
+            (false, true) if y % 2 == 1 => Movement::Along(F64x2::new(0.0, 1.0)),
+            _ => Movement::Fixed,
+        }
+    }
+
+    /// the grid has always let triangles turn inside out (they are scored and drawn the same either way), and still
+    /// does so that fitting one gives the same results as it always has
+    fn keeps_winding(&self) -> bool {
+        false
+    }
+
+    fn vert_key(&self, (x, y): (u32, u32)) -> u64 {
+        (y as u64) << 32 | x as u64
+    }
+
+    fn faces_around(&self, (x, y): (u32, u32)) -> Vec<(usize, Triangle)> {
+        self.triangle_locations_around_point(x, y)
+            .into_iter()
+            .zip(self.triangles_around_point(x, y))
+            .map(|(loc, tri)| (self.triangle_index(&loc), tri))
+            .collect()
+    }
+
+    fn neighbours(&self, (x, y): (u32, u32)) -> Vec<(u32, u32)> {
+        Triangles::neighbours(self, x, y)
+    }
+
+    fn face_index_bound(&self) -> usize {
+        self.triangle_count()
+    }
+
+    fn triangles(&self) -> Vec<Triangle> {
+        self.all_triangles()
+    }
+
+    fn independent_sets(&self) -> Vec<Vec<(u32, u32)>> {
+        Triangles::independent_sets(self)
+    }
+
+    fn size_of_chunk(&self) -> f64 {
+        Triangles::size_of_chunk(self)
+    }
+
+    /// also checks that the grid is the same size as the one [`Mesh::initial`] would make
+    fn check_made_for(&self, width: u32, height: u32, tri_size: f64) -> Result<()> {
+        check_tri_size(self, tri_size)?;
+        let expected = Triangles::for_image(width, height, tri_size);
+        if self.scale_size() != expected.scale_size() || self.real_size() != expected.real_size() {
+            bail!(
+                "the grid is {}x{} triangles covering {}x{} pixels, but a {width}x{height} image needs {}x{} covering {}x{}",
+                self.scale_size().0,
+                self.scale_size().1,
+                self.real_size().0,
+                self.real_size().1,
+                expected.scale_size().0,
+                expected.scale_size().1,
+                expected.real_size().0,
+                expected.real_size().1,
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! adaptive refinement of a [`TriMesh`], so the triangles end up where the detail is.
+//!
+//! each pass splits the triangles that score the worst (through the middle of their longest side), and once the
+//! triangle budget is used up, collapses edges in the flattest parts of the image to make room for more splits.
+//! an edge is only traded for a split if the area around it is a lot flatter than the triangle being split,
+//! so the mesh settles down instead of moving triangles back and forth.
+//!
+//! with a weight map both are weighted by importance, so the triangles end up where the mask is bright
+
+use super::{Mesh, TriMesh};
+use crate::{
+    fit::FitConfig,
+    scoring::{
+        score,
+        stats::{ImageStats, TriangleStats},
+    },
+    triangle::Triangle,
+};
+
+/// triangles with a longest side shorter than this (in pixels) are never split
+const MIN_SPLIT_SIDE: f64 = 4.0;
+/// most triangles that can be split in one pass, as a fraction of the mesh
+const MAX_SPLIT_FRACTION: f64 = 0.1;
+/// how much flatter (in standard deviation) the area around an edge has to be than a triangle, for the edge
+/// to be collapsed to make room to split the triangle
+const COLLAPSE_RATIO: f64 = 0.5;
+
+/// what changed in one refinement pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Refinement {
+    /// number of triangles that were split
+    pub split: usize,
+    /// number of edges that were collapsed
+    pub collapsed: usize,
+}
+
+impl Refinement {
+    /// if the topology of the mesh changed at all
+    pub fn changed(&self) -> bool {
+        self.split > 0 || self.collapsed > 0
+    }
+}
+
+/// runs one refinement pass over the mesh, keeping it to at most `max_triangles` triangles (or as many as it
+/// already has, if it is over)
+pub fn refine(mesh: &mut TriMesh, stats: &ImageStats, cfg: &FitConfig, max_triangles: usize) -> Refinement {
+    let max_splits = ((mesh.face_count() as f64 * MAX_SPLIT_FRACTION) as usize).max(1);
+    let mut candidates = split_candidates(mesh, stats, cfg);
+    candidates.truncate(max_splits);
+
+    // every split adds two triangles (or one on the outside), and every collapse removes two
+    let room = max_triangles.saturating_sub(mesh.face_count()) / 2;
+    let mut refinement = Refinement::default();
+    if candidates.len() > room {
+        let trades = collapse_candidates(mesh, stats)
+            .into_iter()
+            .zip(&candidates[room..])
+            .take_while(|((_, flatness), (_, deviation))| *flatness < COLLAPSE_RATIO * deviation)
+            .map(|((edge, _), _)| edge)
+            .collect::<Vec<_>>();
+        refinement.collapsed = mesh.collapse_edges(trades);
+        if refinement.collapsed > 0 {
+            // the faces have been renumbered
+            candidates = split_candidates(mesh, stats, cfg);
+            candidates.truncate(max_splits);
+        }
+    }
+
+    // the faces split this pass, which are not split again untill their stats are updated
+    let mut split = vec![false; mesh.face_count()];
+    for (face, _) in candidates {
+        if mesh.face_count() + 2 > max_triangles {
+            break;
+        }
+        if split[face] {
+            continue;
+        }
+        let [a, b] = longest_side(mesh, face);
+        if let Some(mid) = mesh.split_edge(a, b) {
+            split.resize(mesh.face_count(), false);
+            for f in mesh.faces_of(mid) {
+                split[f] = true;
+            }
+            refinement.split += 1;
+        }
+    }
+    refinement
+}
+
+/// faces that are worth splitting along with how much their colors vary (times their importance), worst scoring
+/// (after weighting by importance) first
+fn split_candidates(mesh: &TriMesh, stats: &ImageStats, cfg: &FitConfig) -> Vec<(usize, f64)> {
+    let scorer = &*cfg.scorer;
+    let candidates = (0..mesh.face_count())
+        .filter_map(|f| {
+            let tri = mesh.triangle(f);
+            let [a, b] = longest_side(mesh, f);
+            let tri_stats = stats.triangle(tri);
+            let deviation = deviation(&tri_stats);
+            // flat triangles are not going to get any better by being split
+            if (mesh.positions()[a] - mesh.positions()[b]).length() < MIN_SPLIT_SIDE || deviation == 0.0 {
+                return None;
+            }
+            let score = score(tri, stats, cfg.tri_size, scorer);
+            Some((f, deviation * score.weight(), scorer.value(&score), score.weight()))
+        })
+        .collect::<Vec<_>>();
+    // values only mean something compared to each other, so how much worse than the best each one is
+    // is what gets weighted
+    let best = candidates.iter().map(|c| c.2).fold(f64::NEG_INFINITY, f64::max);
+    let mut candidates = candidates
+        .into_iter()
+        .map(|(f, deviation, value, importance)| (f, deviation, (best - value) * importance))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+    candidates.into_iter().map(|(f, deviation, _)| (f, deviation)).collect()
+}
+
+/// edges that could be collapsed, along with how much the colors around them vary (times their importance),
+/// flattest first
+fn collapse_candidates(mesh: &TriMesh, stats: &ImageStats) -> Vec<((usize, usize), f64)> {
+    let face_stats = mesh
+        .triangles()
+        .into_iter()
+        .map(|tri: Triangle| (stats.triangle(tri), stats.importance(tri) * tri.area(), tri.area()))
+        .collect::<Vec<_>>();
+    let mut candidates = vec![];
+    for (a, b) in mesh.edges() {
+        if mesh.on_border()[a] && mesh.on_border()[b] {
+            continue;
+        }
+        let faces = mesh
+            .faces_of(a)
+            .into_iter()
+            .chain(mesh.faces_of(b).into_iter().filter(|f| !mesh.faces()[*f].contains(&a)));
+        let (mut region, mut weight, mut area) = (TriangleStats::default(), 0.0, 0.0);
+        for f in faces {
+            let (face, face_weight, face_area) = face_stats[f];
+            region += face;
+            weight += face_weight;
+            area += face_area;
+        }
+        let importance = if area > 0.0 { weight / area } else { 0.0 };
+        candidates.push(((a, b), deviation(&region) * importance));
+    }
+    candidates.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    candidates
+}
+
+/// overall standard deviation of the colors
+fn deviation(stats: &TriangleStats) -> f64 {
+    stats.variance().iter().sum::<f64>().sqrt()
+}
+
+/// the ends of the longest side of a face
+fn longest_side(mesh: &TriMesh, face: usize) -> [usize; 2] {
+    let corners = mesh.faces()[face];
+    let side = |i: usize| [corners[i], corners[(i + 1) % 3]];
+    let len = |[a, b]: [usize; 2]| (mesh.positions()[a] - mesh.positions()[b]).length();
+    (0..3).map(side).max_by(|a, b| len(*a).total_cmp(&len(*b))).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mesh::{tri_mesh::tests::assert_valid, Movement},
+        triangle::Triangles,
+    };
+
+    /// where the image goes from dark to light
+    const EDGE: f64 = 33.0;
+
+    /// a gentle gradient, with a sharp edge down the middle
+    fn stats() -> ImageStats {
+        ImageStats::from_fn(60, 40, |x, y| {
+            let shade = if x as f64 >= EDGE { 200.0 } else { 40.0 };
+            [shade + x as f64 * 0.2, shade + y as f64 * 0.2, shade]
+        })
+    }
+
+    fn cfg() -> FitConfig {
+        FitConfig {
+            tri_size: 10.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn splits_along_the_edge() {
+        let (stats, cfg) = (stats(), cfg());
+        let mut mesh = TriMesh::from(&Triangles::for_image(60, 40, 10.0));
+        let (verts, faces) = (mesh.vert_count(), mesh.face_count());
+        // enough room that nothing has to be collapsed
+        let max_triangles = faces * 2;
+        let refinement = refine(&mut mesh, &stats, &cfg, max_triangles);
+        assert!(refinement.split > 0);
+        assert_eq!(refinement.collapsed, 0);
+        assert!(mesh.face_count() <= max_triangles);
+        assert_valid(&mesh);
+        // the new verticies are in the middle of the sides of the triangles the edge goes through
+        for v in verts..mesh.vert_count() {
+            let x = mesh.positions()[v].x;
+            assert!((x - EDGE).abs() <= cfg.tri_size, "vertex {v} was added at {x}, away from the edge");
+        }
+    }
+
+    #[test]
+    fn stays_within_budget_and_keeps_the_border() {
+        let (stats, cfg) = (stats(), cfg());
+        let mut mesh = TriMesh::from(&Triangles::for_image(60, 40, 10.0));
+        let max_triangles = mesh.face_count() + 20;
+        let border = |mesh: &TriMesh| {
+            (0..mesh.vert_count())
+                .filter(|v| mesh.on_border()[*v])
+                .map(|v| mesh.positions()[v])
+                .collect::<Vec<_>>()
+        };
+        let corners = (0..mesh.vert_count())
+            .filter(|v| mesh.movement(*v) == Movement::Fixed)
+            .map(|v| mesh.positions()[v])
+            .collect::<Vec<_>>();
+        let before = border(&mesh);
+        let mut collapsed = 0;
+        for _ in 0..10 {
+            collapsed += refine(&mut mesh, &stats, &cfg, max_triangles).collapsed;
+            assert!(mesh.face_count() <= max_triangles);
+            assert_valid(&mesh);
+            // splitting the outside adds verticies to it, but none of the ones there are ever removed or moved
+            let after = border(&mesh);
+            for p in &before {
+                assert!(after.contains(p), "the vertex on the border at {p:?} was collapsed");
+            }
+            for p in &corners {
+                let v = (0..mesh.vert_count()).find(|v| mesh.positions()[*v] == *p).unwrap();
+                assert_eq!(mesh.movement(v), Movement::Fixed, "the corner at {p:?} can move");
+            }
+        }
+        // once the budget is used up, flat parts of the mesh are given up to split more along the edge
+        assert!(collapsed > 0);
+    }
+}
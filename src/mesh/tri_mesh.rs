@@ -0,0 +1,737 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+use super::{Mesh, Movement};
+use crate::{
+    fit::FitConfig,
+    scoring::stats::ImageStats,
+    triangle::{RelVertPos, Triangle, Triangles},
+    vec2::F64x2,
+};
+
+/// a mesh of triangles with any topology, stored as a list of verticies and the corners of each triangle,
+/// connected up as a half-edge mesh.
+///
+/// every side of every face is a half-edge, going from one corner to the next, so half-edge `3 * f + i` starts
+/// at corner `i` of face `f`. an edge inside the mesh is made of two half-edges going opposite ways (twins),
+/// and an edge on the outside only has one.
+///
+/// every triangle has its corners in the same winding order as the grid (see [`Triangle::signed_area`]),
+/// which is kept that way as the mesh is changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "Parts", into = "Parts")]
+pub struct TriMesh {
+    verts: Vec<F64x2>,
+    /// verticies on the outside of the mesh, which can only move along it (see [`Movement::Along`])
+    on_border: Vec<bool>,
+    faces: Vec<[usize; 3]>,
+    /// the half-edge going the other way along the same edge, for each half-edge
+    twin: Vec<Option<usize>>,
+    /// a half-edge starting at each vertex (if it is the corner of anything)
+    vert_edge: Vec<Option<usize>>,
+    size_of_chunk: f64,
+}
+
+/// how far from a straight line (as the sine of the angle between them) the sides of the mesh on either side of a
+/// vertex on the border can be for it to slide along them
+const STRAIGHT_TOLERANCE: f64 = 1e-9;
+
+/// everything needed to rebuild a [`TriMesh`], which is what gets serialized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Parts {
+    verts: Vec<F64x2>,
+    /// called `fixed` in older checkpoints
+    #[serde(rename = "fixed")]
+    on_border: Vec<bool>,
+    faces: Vec<[usize; 3]>,
+    size_of_chunk: f64,
+}
+
+impl PartialEq for TriMesh {
+    /// the half-edges all follow from the faces, so they are left out
+    fn eq(&self, other: &Self) -> bool {
+        self.verts == other.verts
+            && self.on_border == other.on_border
+            && self.faces == other.faces
+            && self.size_of_chunk == other.size_of_chunk
+    }
+}
+
+impl From<Parts> for TriMesh {
+    fn from(parts: Parts) -> Self {
+        Self::from_parts(parts.verts, parts.on_border, parts.faces, parts.size_of_chunk)
+    }
+}
+
+impl From<TriMesh> for Parts {
+    fn from(mesh: TriMesh) -> Self {
+        Self {
+            verts: mesh.verts,
+            on_border: mesh.on_border,
+            faces: mesh.faces,
+            size_of_chunk: mesh.size_of_chunk,
+        }
+    }
+}
+
+impl TriMesh {
+    /// builds a mesh from its raw parts (see [`TriMesh::positions`], [`TriMesh::on_border`], [`TriMesh::faces`],
+    /// and [`Mesh::size_of_chunk`]).
+    ///
+    /// panics if a face uses a vertex twice, or two faces have the same side going the same way
+    /// (which would mean they overlap)
+    pub fn from_parts(verts: Vec<F64x2>, on_border: Vec<bool>, faces: Vec<[usize; 3]>, size_of_chunk: f64) -> Self {
+        assert_eq!(verts.len(), on_border.len());
+        let mut mesh = Self {
+            twin: vec![],
+            vert_edge: vec![],
+            verts,
+            on_border,
+            faces,
+            size_of_chunk,
+        };
+        mesh.rebuild_connectivity();
+        mesh
+    }
+
+    fn rebuild_connectivity(&mut self) {
+        let mut half_edges = HashMap::with_capacity(self.faces.len() * 3);
+        self.vert_edge = vec![None; self.verts.len()];
+        for h in 0..self.faces.len() * 3 {
+            let (from, to) = (self.origin(h), self.dest(h));
+            assert_ne!(from, to, "face {} uses vertex {from} twice", h / 3);
+            let old = half_edges.insert((from, to), h);
+            assert!(old.is_none(), "more than one face has the side from {from} to {to}");
+            self.vert_edge[from] = Some(h);
+        }
+        self.twin = (0..self.faces.len() * 3)
+            .map(|h| half_edges.get(&(self.dest(h), self.origin(h))).copied())
+            .collect();
+    }
+
+    /// the position of every vertex
+    pub fn positions(&self) -> &[F64x2] {
+        &self.verts
+    }
+
+    /// the top left and bottom right corners of the box around every vertex
+    pub fn bounds(&self) -> (F64x2, F64x2) {
+        self.verts.iter().fold(
+            (F64x2::new(f64::INFINITY, f64::INFINITY), F64x2::new(f64::NEG_INFINITY, f64::NEG_INFINITY)),
+            |(min, max), p| (F64x2::new(min.x.min(p.x), min.y.min(p.y)), F64x2::new(max.x.max(p.x), max.y.max(p.y))),
+        )
+    }
+
+    /// which verticies are on the outside of the mesh. these can only slide along it (see [`Mesh::movement`]), and
+    /// are never removed or moved when collapsing edges
+    pub fn on_border(&self) -> &[bool] {
+        &self.on_border
+    }
+
+    /// the corners of every triangle
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    pub fn vert_count(&self) -> usize {
+        self.verts.len()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    /// the triangle for a face
+    pub fn triangle(&self, face: usize) -> Triangle {
+        let [a, b, c] = self.faces[face];
+        Triangle(self.verts[a], self.verts[b], self.verts[c])
+    }
+
+    /// the vertex a half-edge starts at
+    pub fn origin(&self, h: usize) -> usize {
+        self.faces[h / 3][h % 3]
+    }
+
+    /// the vertex a half-edge ends at
+    pub fn dest(&self, h: usize) -> usize {
+        self.origin(self.next(h))
+    }
+
+    /// the next half-edge around the same face
+    pub fn next(&self, h: usize) -> usize {
+        h - h % 3 + (h + 1) % 3
+    }
+
+    /// the previous half-edge around the same face
+    pub fn prev(&self, h: usize) -> usize {
+        h - h % 3 + (h + 2) % 3
+    }
+
+    /// the half-edge going the other way along the same edge, or `None` if the edge is on the outside of the mesh
+    pub fn twin(&self, h: usize) -> Option<usize> {
+        self.twin[h]
+    }
+
+    /// the face a half-edge is a side of
+    pub fn face_of(&self, h: usize) -> usize {
+        h / 3
+    }
+
+    /// the half-edge from `a` to `b`, if there is one
+    pub fn half_edge(&self, a: usize, b: usize) -> Option<usize> {
+        self.outgoing(a).into_iter().find(|h| self.dest(*h) == b)
+    }
+
+    /// every half-edge starting at `v`, in order around it.
+    ///
+    /// for a vertex on the outside of the mesh, this starts from the side on the outside
+    pub fn outgoing(&self, v: usize) -> Vec<usize> {
+        let Some(first) = self.vert_edge[v] else {
+            return vec![];
+        };
+        let mut start = first;
+        while let Some(t) = self.twin[start] {
+            start = self.next(t);
+            if start == first {
+                break;
+            }
+        }
+        let mut outgoing = vec![start];
+        let mut h = start;
+        while let Some(t) = self.twin[self.prev(h)] {
+            if t == start {
+                break;
+            }
+            outgoing.push(t);
+            h = t;
+        }
+        outgoing
+    }
+
+    /// if `v` is on the outside of the mesh (or not part of it at all)
+    pub fn is_boundary(&self, v: usize) -> bool {
+        self.outgoing(v).first().is_none_or(|h| self.twin[*h].is_none())
+    }
+
+    /// every edge, each exactly once, as the verticies at either end
+    pub fn edges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.faces.len() * 3)
+            .filter(|h| self.twin[*h].is_none_or(|t| *h < t))
+            .map(|h| (self.origin(h), self.dest(h)))
+    }
+
+    /// the faces with `v` as a corner, in order around it
+    pub fn faces_of(&self, v: usize) -> Vec<usize> {
+        self.outgoing(v).into_iter().map(|h| self.face_of(h)).collect()
+    }
+
+    /// verticies that share an edge with `v`, in order around it
+    pub fn neighbours(&self, v: usize) -> Vec<usize> {
+        let outgoing = self.outgoing(v);
+        let mut neighbours = outgoing.iter().map(|h| self.dest(*h)).collect::<Vec<_>>();
+        // on the outside, the last side going in to `v` does not have a twin coming back out
+        if let Some(last) = outgoing.last().map(|h| self.prev(*h)) {
+            if self.twin[last].is_none() {
+                neighbours.push(self.origin(last));
+            }
+        }
+        neighbours
+    }
+
+    /// the faces that have the edge from `a` to `b` as a side (one on the outside of the mesh, and two inside it)
+    pub fn faces_on_edge(&self, a: usize, b: usize) -> Vec<usize> {
+        match self.half_edge(a, b).or_else(|| self.half_edge(b, a)) {
+            Some(h) => std::iter::once(h).chain(self.twin[h]).map(|h| self.face_of(h)).collect(),
+            None => vec![],
+        }
+    }
+
+    /// adds a vertex in the middle of the edge from `a` to `b`, splitting the triangles on either side of it in two.
+    /// returns the new vertex, or `None` if there is no such edge.
+    ///
+    /// if the edge is on the outside of the mesh, the new vertex is on the border too
+    pub fn split_edge(&mut self, a: usize, b: usize) -> Option<usize> {
+        let h = self.half_edge(a, b).or_else(|| self.half_edge(b, a))?;
+        let sides = [Some(h), self.twin[h]];
+        let mid = self.verts.len();
+        self.verts.push((self.verts[a] + self.verts[b]) / 2.0);
+        self.on_border.push(sides[1].is_none());
+        self.vert_edge.push(None);
+        // the halves of the split edge on each side, from its start to the middle and from the middle to its end
+        let mut halves = vec![];
+        for h in sides.into_iter().flatten() {
+            // face `f` goes from -> to -> other, and becomes from -> mid -> other, with mid -> to -> other added
+            let f = self.face_of(h);
+            let (to, other) = (self.dest(h), self.origin(self.prev(h)));
+            let mid_other = self.next(h);
+            let g = self.faces.len();
+            self.faces[f][mid_other % 3] = mid;
+            self.faces.push([mid, to, other]);
+            let (mid_to, to_other, other_mid) = (3 * g, 3 * g + 1, 3 * g + 2);
+            // the side from `to` to `other` is now part of the new face
+            let outer = self.twin[mid_other];
+            self.twin.extend([None, outer, Some(mid_other)]);
+            if let Some(o) = outer {
+                self.twin[o] = Some(to_other);
+            }
+            self.twin[mid_other] = Some(other_mid);
+            self.vert_edge[to] = Some(to_other);
+            self.vert_edge[mid] = Some(mid_other);
+            halves.push((h, mid_to));
+        }
+        if let [(start_a, mid_b), (start_b, mid_a)] = halves[..] {
+            self.twin[start_a] = Some(mid_a);
+            self.twin[mid_a] = Some(start_a);
+            self.twin[start_b] = Some(mid_b);
+            self.twin[mid_b] = Some(start_b);
+        }
+        Some(mid)
+    }
+
+    /// the verticies across from the edge from `a` to `b` (which must be inside the mesh), as `[a, b, c, d]`
+    /// where `a -> b -> c` and `b -> a -> d` are the two triangles on either side of it
+    pub fn quad(&self, a: usize, b: usize) -> Option<[usize; 4]> {
+        let h = self.half_edge(a, b)?;
+        let t = self.twin[h]?;
+        Some([a, b, self.origin(self.prev(h)), self.origin(self.prev(t))])
+    }
+
+    /// swaps the edge from `a` to `b` for the one between the verticies across from it, turning the triangles
+    /// `a -> b -> c` and `b -> a -> d` into `a -> d -> c` and `d -> b -> c`. both faces keep their index.
+    ///
+    /// returns `false` (and does nothing) if there is no such edge inside the mesh, or flipping it would
+    /// turn a triangle inside out or give two triangles the same edge
+    pub fn flip_edge(&mut self, a: usize, b: usize) -> bool {
+        let Some(h) = self.half_edge(a, b).or_else(|| self.half_edge(b, a)) else {
+            return false;
+        };
+        let Some(t) = self.twin[h] else {
+            return false;
+        };
+        let (a, b) = (self.origin(h), self.dest(h));
+        let (c, d) = (self.origin(self.prev(h)), self.origin(self.prev(t)));
+        let new = [[a, d, c], [d, b, c]];
+        if c == d
+            || self.half_edge(c, d).is_some()
+            || new.iter().any(|[x, y, z]| Triangle(self.verts[*x], self.verts[*y], self.verts[*z]).signed_area() <= 0.0)
+        {
+            return false;
+        }
+        // the sides of the two faces that are not the flipped edge, and what is on the other side of them
+        let [a_d, d_b, b_c, c_a] = [self.next(t), self.prev(t), self.next(h), self.prev(h)].map(|e| self.twin[e]);
+        let (f, g) = (self.face_of(h), self.face_of(t));
+        self.faces[f] = new[0];
+        self.faces[g] = new[1];
+        let (f, g) = (3 * f, 3 * g);
+        // f is a -> d, d -> c, c -> a, and g is d -> b, b -> c, c -> d
+        for (e, twin) in [(f, a_d), (f + 2, c_a), (g, d_b), (g + 1, b_c)] {
+            self.twin[e] = twin;
+            if let Some(twin) = twin {
+                self.twin[twin] = Some(e);
+            }
+        }
+        self.twin[f + 1] = Some(g + 2);
+        self.twin[g + 2] = Some(f + 1);
+        self.vert_edge[a] = Some(f);
+        self.vert_edge[b] = Some(g + 1);
+        self.vert_edge[c] = Some(f + 2);
+        self.vert_edge[d] = Some(g);
+        true
+    }
+
+    /// merges the two ends of each edge into one vertex, removing the triangles on either side of it.
+    /// returns how many edges were collapsed.
+    ///
+    /// edges are skipped if collapsing them would flip a triangle, fold the mesh over on itself, or move a
+    /// vertex on the border, as well as if they are next to an edge that was just collapsed.
+    /// this renumbers the verticies and faces afterwards, so any indices into the mesh are no longer valid
+    pub fn collapse_edges(&mut self, edges: impl IntoIterator<Item = (usize, usize)>) -> usize {
+        let mut dead_verts = vec![false; self.verts.len()];
+        let mut dead_faces = vec![false; self.faces.len()];
+        // verticies around an edge that has been collapsed. the half-edges around these are out of date untill
+        // the end, but the ones around every other vertex are left as they were
+        let mut touched = vec![false; self.verts.len()];
+        let mut collapsed = 0;
+        for (a, b) in edges {
+            if touched[a] || touched[b] || dead_verts[a] || dead_verts[b] {
+                continue;
+            }
+            // `b` is the one removed, and `a` moves to where they meet
+            let (keep, remove) = match (self.on_border[a], self.on_border[b]) {
+                (true, true) => continue,
+                (false, true) => (b, a),
+                _ => (a, b),
+            };
+            let pos = if self.on_border[keep] {
+                self.verts[keep]
+            } else {
+                (self.verts[keep] + self.verts[remove]) / 2.0
+            };
+            if !self.can_collapse(keep, remove, pos) {
+                continue;
+            }
+
+            for f in self.faces_on_edge(keep, remove) {
+                dead_faces[f] = true;
+            }
+            for n in self.neighbours(keep).into_iter().chain(self.neighbours(remove)) {
+                touched[n] = true;
+            }
+            for f in self.faces_of(remove) {
+                self.faces[f].iter_mut().filter(|v| **v == remove).for_each(|v| *v = keep);
+            }
+            self.verts[keep] = pos;
+            dead_verts[remove] = true;
+            collapsed += 1;
+        }
+        if collapsed > 0 {
+            self.remove_dead(&dead_verts, &dead_faces);
+        }
+        collapsed
+    }
+
+    /// if `remove` can be merged into `keep` at `pos`, without changing the topology of the rest of the mesh
+    fn can_collapse(&self, keep: usize, remove: usize, pos: F64x2) -> bool {
+        let shared = self.faces_on_edge(keep, remove);
+        if shared.len() != 2 {
+            return false;
+        }
+        // the only verticies next to both ends should be the ones across from the edge,
+        // otherwise two triangles would end up on top of each other
+        let opposite = shared
+            .iter()
+            .flat_map(|f| self.faces[*f])
+            .filter(|v| *v != keep && *v != remove)
+            .collect::<Vec<_>>();
+        let keep_neighbours = self.neighbours(keep);
+        let common = self
+            .neighbours(remove)
+            .into_iter()
+            .filter(|n| keep_neighbours.contains(n))
+            .count();
+        if common != 2 || opposite[0] == opposite[1] {
+            return false;
+        }
+        // a vertex across from the edge that is only in the face being removed would be left on its own.
+        // this is when both of its other sides are on the outside
+        let h = self.half_edge(keep, remove).or_else(|| self.half_edge(remove, keep)).unwrap();
+        for h in [Some(h), self.twin[h]].into_iter().flatten() {
+            if self.twin[self.next(h)].is_none() && self.twin[self.prev(h)].is_none() {
+                return false;
+            }
+        }
+        // and every triangle that is left has to keep its winding order
+        self.faces_of(keep)
+            .into_iter()
+            .chain(self.faces_of(remove))
+            .filter(|f| !shared.contains(f))
+            .all(|f| {
+                let [a, b, c] = self.faces[f].map(|v| if v == keep || v == remove { pos } else { self.verts[v] });
+                Triangle(a, b, c).signed_area() > 0.0
+            })
+    }
+
+    fn remove_dead(&mut self, dead_verts: &[bool], dead_faces: &[bool]) {
+        let mut new_index = vec![usize::MAX; self.verts.len()];
+        let mut next = 0;
+        for (v, dead) in dead_verts.iter().enumerate() {
+            if !dead {
+                new_index[v] = next;
+                next += 1;
+            }
+        }
+        let keep = |v: usize| !dead_verts[v];
+        self.verts = (0..self.verts.len()).filter(|v| keep(*v)).map(|v| self.verts[v]).collect();
+        self.on_border = (0..self.on_border.len()).filter(|v| keep(*v)).map(|v| self.on_border[v]).collect();
+        self.faces = self
+            .faces
+            .iter()
+            .zip(dead_faces)
+            .filter(|(_, dead)| !**dead)
+            .map(|(face, _)| face.map(|v| new_index[v]))
+            .collect();
+        self.rebuild_connectivity();
+    }
+}
+
+impl From<&Triangles> for TriMesh {
+    /// the same mesh as the grid, with the verticies in row order and the faces in the order of
+    /// [`Triangles::all_triangles`]
+    fn from(tris: &Triangles) -> Self {
+        use RelVertPos::*;
+
+        let mut row_start = vec![];
+        let mut verts = vec![];
+        let mut on_border = vec![];
+        for (y, row) in tris.rows().iter().enumerate() {
+            row_start.push(verts.len());
+            for (x, vert) in row.iter().enumerate() {
+                verts.push(*vert);
+                on_border.push(tris.vert_is_edge(x as u32, y as u32));
+            }
+        }
+        let index = |(x, y): (u32, u32)| row_start[y as usize] + x as usize;
+
+        let mut faces = vec![];
+        for (y, row) in tris.rows().iter().enumerate() {
+            for x in 0..row.len() {
+                let v = (x as u32, y as u32);
+                for (a, b) in [(DownRight, DownLeft), (Right, DownRight)] {
+                    if let (Some(a), Some(b)) = (tris.pos_rel(v.0, v.1, a), tris.pos_rel(v.0, v.1, b)) {
+                        faces.push([index(v), index(a), index(b)]);
+                    }
+                }
+            }
+        }
+        Self::from_parts(verts, on_border, faces, tris.size_of_chunk())
+    }
+}
+
+impl From<Triangles> for TriMesh {
+    fn from(tris: Triangles) -> Self {
+        Self::from(&tris)
+    }
+}
+
+impl Mesh for TriMesh {
+    type Vert = usize;
+
+    fn initial(image: &RgbImage, cfg: &FitConfig, seed: u64) -> Self {
+        super::init::initial_mesh(image, cfg, seed)
+    }
+
+    /// the verticies on the outside never leave it, so the mesh has to cover the same area as one
+    /// [`Mesh::initial`] would make: the image itself, or the (padded) grid for it
+    fn check_made_for(&self, width: u32, height: u32, tri_size: f64) -> Result<()> {
+        super::check_tri_size(self, tri_size)?;
+        let (min, max) = self.bounds();
+        let grid = Self::from(Triangles::for_image(width, height, tri_size)).bounds();
+        let close = |a: F64x2, b: F64x2| (a - b).length() <= 1e-6 * tri_size.max(1.0);
+        let fits = [(F64x2::zero(), F64x2::new(width as f64, height as f64)), grid]
+            .iter()
+            .any(|&(lo, hi)| close(min, lo) && close(max, hi));
+        if !fits {
+            bail!(
+                "the mesh covers ({}, {}) to ({}, {}), which does not match a {width}x{height} image",
+                min.x,
+                min.y,
+                max.x,
+                max.y
+            );
+        }
+        Ok(())
+    }
+
+    fn verts(&self) -> Vec<usize> {
+        (0..self.verts.len()).collect()
+    }
+
+    fn vert(&self, v: usize) -> F64x2 {
+        self.verts[v]
+    }
+
+    fn set_vert(&mut self, v: usize, pos: F64x2) {
+        self.verts[v] = pos;
+    }
+
+    /// verticies on the border slide along the outside of the mesh where it is straight (so the ones next to them
+    /// along it are in a line with them). where the outside bends inwards at a vertex, like the ends of the odd rows
+    /// of the grid, it slides along the line between those two instead, so it can not fold over the triangles next to
+    /// it. anywhere else is a corner.
+    ///
+    /// the direction always points right (or down, if it is straight up and down), the same as for [`Triangles`]
+    fn movement(&self, v: usize) -> Movement {
+        if !self.on_border[v] {
+            return Movement::Free;
+        }
+        let outgoing = self.outgoing(v);
+        let (Some(&first), Some(&last)) = (outgoing.first(), outgoing.last()) else {
+            return Movement::Fixed;
+        };
+        // the outgoing half-edges start at the one along the outside, and the one before the last comes back in
+        let incoming = self.prev(last);
+        if self.twin(first).is_some() || self.twin(incoming).is_some() {
+            return Movement::Fixed;
+        }
+        let (pos, next, prev) = (self.verts[v], self.verts[self.dest(first)], self.verts[self.origin(incoming)]);
+        let (a, b) = (next - pos, prev - pos);
+        let straight = a.cross_2v(b).abs() <= STRAIGHT_TOLERANCE * a.length() * b.length();
+        let dir = if straight && a.dot(b) < 0.0 {
+            a
+        } else if !straight && Triangle(prev, pos, next).signed_area() < 0.0 {
+            // the outside goes around the faces the same way they are wound, so it bends inwards where it turns
+            // the other way
+            next - prev
+        } else {
+            return Movement::Fixed;
+        };
+        let dir = dir / dir.length();
+        if dir.x < 0.0 || (dir.x == 0.0 && dir.y < 0.0) {
+            Movement::Along(-dir)
+        } else {
+            Movement::Along(dir)
+        }
+    }
+
+    fn vert_key(&self, v: usize) -> u64 {
+        v as u64
+    }
+
+    fn faces_around(&self, v: usize) -> Vec<(usize, Triangle)> {
+        self.outgoing(v)
+            .into_iter()
+            .map(|h| {
+                let [a, b, c] = [h, self.next(h), self.prev(h)].map(|h| self.verts[self.origin(h)]);
+                (self.face_of(h), Triangle(a, b, c))
+            })
+            .collect()
+    }
+
+    fn neighbours(&self, v: usize) -> Vec<usize> {
+        TriMesh::neighbours(self, v)
+    }
+
+    fn edges(&self) -> Vec<(usize, usize)> {
+        TriMesh::edges(self).collect()
+    }
+
+    fn face_index_bound(&self) -> usize {
+        self.faces.len()
+    }
+
+    fn triangles(&self) -> Vec<Triangle> {
+        (0..self.faces.len()).map(|f| self.triangle(f)).collect()
+    }
+
+    fn independent_sets(&self) -> Vec<Vec<usize>> {
+        // greedy coloring, in vertex order
+        let mut colors: Vec<Option<usize>> = vec![None; self.verts.len()];
+        let mut sets: Vec<Vec<usize>> = vec![];
+        for v in 0..self.verts.len() {
+            let taken = self
+                .neighbours(v)
+                .into_iter()
+                .filter_map(|n| colors[n])
+                .collect::<Vec<_>>();
+            let color = (0..).find(|c| !taken.contains(c)).unwrap();
+            colors[v] = Some(color);
+            if color >= sets.len() {
+                sets.push(vec![]);
+            }
+            sets[color].push(v);
+        }
+        sets
+    }
+
+    fn size_of_chunk(&self) -> f64 {
+        self.size_of_chunk
+    }
+
+    fn refine(&mut self, stats: &ImageStats, cfg: &FitConfig, max_triangles: usize) -> super::Refinement {
+        super::refine::refine(self, stats, cfg, max_triangles)
+    }
+
+    fn flip_edges(&mut self, stats: &ImageStats, cfg: &FitConfig, criterion: super::FlipCriterion) -> usize {
+        super::flip::flip_edges(self, stats, cfg, criterion)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// the grid, with the verticies inside it moved about a bit so that the triangles are all different
+    fn mesh() -> TriMesh {
+        let mut mesh = TriMesh::from(&Triangles::new(60, 40, 10.0));
+        for v in 0..mesh.vert_count() {
+            if !mesh.on_border[v] {
+                let offset = F64x2::new(((v * 7) % 5) as f64 * 0.4 - 0.8, ((v * 3) % 7) as f64 * 0.3 - 0.9);
+                mesh.verts[v] += offset;
+            }
+        }
+        mesh
+    }
+
+    /// checks that every twin goes the other way along the same edge (and has the first as its twin), that every
+    /// edge used both ways has twins, and that every triangle is wound the same way as the grid
+    pub(in crate::mesh) fn assert_valid(mesh: &TriMesh) {
+        for h in 0..mesh.face_count() * 3 {
+            let (from, to) = (mesh.origin(h), mesh.dest(h));
+            match mesh.twin(h) {
+                Some(t) => {
+                    assert_eq!(mesh.twin(t), Some(h), "twin of half-edge {h} does not point back to it");
+                    assert_eq!((mesh.origin(t), mesh.dest(t)), (to, from), "half-edge {h} and its twin do not match");
+                }
+                None => assert_eq!(mesh.half_edge(to, from), None, "half-edge {h} is missing its twin"),
+            }
+        }
+        for v in 0..mesh.vert_count() {
+            if let Some(h) = mesh.vert_edge[v] {
+                assert_eq!(mesh.origin(h), v);
+            }
+        }
+        for f in 0..mesh.face_count() {
+            assert!(mesh.triangle(f).signed_area() > 0.0, "face {f} is wound the wrong way");
+        }
+    }
+
+    #[test]
+    fn movement_matches_grid() {
+        for (width, height) in [(60, 40), (65, 43)] {
+            let grid = Triangles::for_image(width, height, 10.0);
+            let mesh = TriMesh::from(&grid);
+            let mut v = 0;
+            for (y, row) in grid.rows().iter().enumerate() {
+                for x in 0..row.len() {
+                    let vert = (x as u32, y as u32);
+                    assert_eq!(mesh.movement(v), grid.movement(vert), "vertex {vert:?} of the {width}x{height} grid");
+                    v += 1;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn twins_are_symmetric() {
+        let mesh = mesh();
+        assert_valid(&mesh);
+        let outside = (0..mesh.face_count() * 3).filter(|h| mesh.twin(*h).is_none()).count();
+        assert!(outside > 0 && outside < mesh.face_count() * 3);
+    }
+
+    #[test]
+    fn flips_keep_winding() {
+        let mut mesh = mesh();
+        let mut flipped = 0;
+        for (a, b) in mesh.edges().collect::<Vec<_>>() {
+            if mesh.flip_edge(a, b) {
+                flipped += 1;
+                assert_valid(&mesh);
+            }
+        }
+        assert!(flipped > 0);
+    }
+
+    #[test]
+    fn splits_keep_winding() {
+        let mut mesh = mesh();
+        for (a, b) in mesh.edges().step_by(3).collect::<Vec<_>>() {
+            assert!(mesh.split_edge(a, b).is_some());
+        }
+        assert_valid(&mesh);
+    }
+
+    #[test]
+    fn collapses_keep_winding() {
+        let mut mesh = mesh();
+        let faces = mesh.face_count();
+        let collapsed = mesh.collapse_edges(mesh.edges().collect::<Vec<_>>());
+        assert!(collapsed > 0);
+        // each collapse removes the two triangles on either side of the edge
+        assert_eq!(mesh.face_count(), faces - 2 * collapsed);
+        assert_valid(&mesh);
+    }
+}
@@ -0,0 +1,463 @@
+//! native on-disk format for [`Triangles`] and [`TriMesh`], so an optimized mesh can be saved and re-exported later
+//! without running the optimizer again.
+//!
+//! there are two variants, which both store exactly the same information and round-trip exactly:
+//!
+//! ## JSON (`.json`)
+//!
+//! ```json
+//! {
+//!   "format": "trifit-mesh",
+//!   "version": 1,
+//!   "size_of_chunk": 15.0,
+//!   "scale_size": [60, 40],
+//!   "real_size": [900, 600],
+//!   "rows": [[[-7.5, 0.0], [7.5, 0.0], ...], ...]
+//! }
+//! ```
+//!
+//! `rows` holds each row of verticies (top to bottom) as `[x, y]` pairs, in pixels.
+//!
+//! ## binary (any other extension, `.trimesh` by convention)
+//!
+//! all values are little-endian
+//!
+//! | field            | type                        |
+//! |------------------|-----------------------------|
+//! | magic            | `b"TRIMESH\0"`              |
+//! | version          | `u32` (currently `1`)       |
+//! | size_of_chunk    | `f64`                       |
+//! | scale_size       | `u32`, `u32`                |
+//! | real_size        | `u32`, `u32`                |
+//! | number of rows   | `u32`                       |
+//! | each row         | `u32` length, then `length` × (`f64` x, `f64` y) |
+//!
+//! ## general meshes
+//!
+//! a [`TriMesh`] is stored the same way, but with a list of verticies and the corners of each triangle
+//! (as indices into the verticies) instead of rows. the JSON variant has a `format` of `"trifit-trimesh"`:
+//!
+//! ```json
+//! {
+//!   "format": "trifit-trimesh",
+//!   "version": 1,
+//!   "size_of_chunk": 15.0,
+//!   "verticies": [[-7.5, 0.0], [7.5, 0.0], ...],
+//!   "fixed": [true, true, ...],
+//!   "faces": [[0, 62, 61], ...]
+//! }
+//! ```
+//!
+//! and the binary variant is
+//!
+//! | field              | type                                      |
+//! |--------------------|-------------------------------------------|
+//! | magic              | `b"TRIFACE\0"`                            |
+//! | version            | `u32` (currently `1`)                     |
+//! | size_of_chunk      | `f64`                                     |
+//! | number of verticies| `u32`                                     |
+//! | each vertex        | `f64` x, `f64` y, `u8` on border (0 or 1) |
+//! | number of faces    | `u32`                                     |
+//! | each face          | 3 × `u32`                                 |
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mesh::{Mesh, TriMesh},
+    triangle::Triangles,
+    vec2::F64x2,
+};
+
+pub const MAGIC: &[u8; 8] = b"TRIMESH\0";
+pub const FORMAT_NAME: &str = "trifit-mesh";
+pub const VERSION: u32 = 1;
+
+pub const TRIMESH_MAGIC: &[u8; 8] = b"TRIFACE\0";
+pub const TRIMESH_FORMAT_NAME: &str = "trifit-trimesh";
+
+/// a kind of mesh that can be saved in this format
+pub trait MeshFile: Sized {
+    /// the magic bytes the binary variant starts with
+    const MAGIC: &'static [u8; 8];
+
+    fn write_json(&self, writer: &mut dyn Write) -> Result<()>;
+    fn read_json(reader: &mut dyn Read) -> Result<Self>;
+    fn write_binary(&self, writer: &mut dyn Write) -> Result<()>;
+    fn read_binary(reader: &mut dyn Read) -> Result<Self>;
+}
+
+impl MeshFile for Triangles {
+    const MAGIC: &'static [u8; 8] = MAGIC;
+
+    fn write_json(&self, writer: &mut dyn Write) -> Result<()> {
+        write_json(self, writer)
+    }
+
+    fn read_json(reader: &mut dyn Read) -> Result<Self> {
+        read_json(reader)
+    }
+
+    fn write_binary(&self, writer: &mut dyn Write) -> Result<()> {
+        write_binary(self, writer)
+    }
+
+    fn read_binary(reader: &mut dyn Read) -> Result<Self> {
+        read_binary(reader)
+    }
+}
+
+impl MeshFile for TriMesh {
+    const MAGIC: &'static [u8; 8] = TRIMESH_MAGIC;
+
+    fn write_json(&self, writer: &mut dyn Write) -> Result<()> {
+        write_trimesh_json(self, writer)
+    }
+
+    fn read_json(reader: &mut dyn Read) -> Result<Self> {
+        read_trimesh_json(reader)
+    }
+
+    fn write_binary(&self, writer: &mut dyn Write) -> Result<()> {
+        write_trimesh_binary(self, writer)
+    }
+
+    fn read_binary(reader: &mut dyn Read) -> Result<Self> {
+        read_trimesh_binary(reader)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonMesh {
+    format: String,
+    version: u32,
+    size_of_chunk: f64,
+    scale_size: (u32, u32),
+    real_size: (u32, u32),
+    rows: Vec<Vec<[f64; 2]>>,
+}
+
+/// saves the mesh to `path`, as JSON if the extension is `.json` and binary otherwise
+pub fn save<M: MeshFile>(tris: &M, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer = BufWriter::new(File::create(path).with_context(|| format!("failed to create {path:?}"))?);
+    if is_json_path(path) {
+        tris.write_json(&mut writer)?;
+    } else {
+        tris.write_binary(&mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// loads a mesh from `path`, in either variant (detected from the contents)
+pub fn load<M: MeshFile>(path: impl AsRef<Path>) -> Result<M> {
+    let path = path.as_ref();
+    let mut bytes = vec![];
+    BufReader::new(File::open(path).with_context(|| format!("failed to open {path:?}"))?).read_to_end(&mut bytes)?;
+    if bytes.starts_with(M::MAGIC) {
+        M::read_binary(&mut bytes.as_slice())
+    } else {
+        M::read_json(&mut bytes.as_slice())
+    }
+    .with_context(|| format!("{path:?} is not a valid mesh file"))
+}
+
+/// if the file at `path` holds a [`TriMesh`] rather than a grid
+pub fn is_trimesh(path: impl AsRef<Path>) -> Result<bool> {
+    let path = path.as_ref();
+    let mut bytes = vec![];
+    BufReader::new(File::open(path).with_context(|| format!("failed to open {path:?}"))?).read_to_end(&mut bytes)?;
+    if bytes.starts_with(TRIMESH_MAGIC) {
+        return Ok(true);
+    }
+    #[derive(Deserialize)]
+    struct Header {
+        format: String,
+    }
+    Ok(serde_json::from_slice::<Header>(&bytes).is_ok_and(|header| header.format == TRIMESH_FORMAT_NAME))
+}
+
+fn is_json_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+pub fn write_json(tris: &Triangles, writer: impl Write) -> Result<()> {
+    let mesh = JsonMesh {
+        format: FORMAT_NAME.to_string(),
+        version: VERSION,
+        size_of_chunk: tris.size_of_chunk(),
+        scale_size: tris.scale_size(),
+        real_size: tris.real_size(),
+        rows: tris
+            .rows()
+            .iter()
+            .map(|row| row.iter().map(|v| [v.x, v.y]).collect())
+            .collect(),
+    };
+    serde_json::to_writer(writer, &mesh)?;
+    Ok(())
+}
+
+pub fn read_json(reader: impl Read) -> Result<Triangles> {
+    let mesh: JsonMesh = serde_json::from_reader(reader)?;
+    ensure!(mesh.format == FORMAT_NAME, "unknown format {:?}", mesh.format);
+    ensure!(mesh.version == VERSION, "unsupported version {}", mesh.version);
+    let rows = mesh
+        .rows
+        .into_iter()
+        .map(|row| row.into_iter().map(F64x2::from).collect())
+        .collect::<Vec<_>>();
+    check_grid(&rows, mesh.scale_size)?;
+    Ok(Triangles::from_parts(rows, mesh.scale_size, mesh.real_size, mesh.size_of_chunk))
+}
+
+pub fn write_binary(tris: &Triangles, mut writer: impl Write) -> Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&tris.size_of_chunk().to_le_bytes())?;
+    for v in [tris.scale_size(), tris.real_size()] {
+        writer.write_all(&v.0.to_le_bytes())?;
+        writer.write_all(&v.1.to_le_bytes())?;
+    }
+    writer.write_all(&u32::try_from(tris.rows().len())?.to_le_bytes())?;
+    for row in tris.rows() {
+        writer.write_all(&u32::try_from(row.len())?.to_le_bytes())?;
+        for vert in row {
+            writer.write_all(&vert.x.to_le_bytes())?;
+            writer.write_all(&vert.y.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f64(reader: &mut impl Read) -> Result<f64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+pub fn read_binary(mut reader: impl Read) -> Result<Triangles> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("missing magic bytes");
+    }
+    let version = read_u32(&mut reader)?;
+    ensure!(version == VERSION, "unsupported version {version}");
+    let size_of_chunk = read_f64(&mut reader)?;
+    let scale_size = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+    let real_size = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+    let num_rows = read_u32(&mut reader)?;
+    let mut rows = vec![];
+    for _ in 0..num_rows {
+        let len = read_u32(&mut reader)?;
+        let mut row = vec![];
+        for _ in 0..len {
+            row.push(F64x2::new(read_f64(&mut reader)?, read_f64(&mut reader)?));
+        }
+        rows.push(row);
+    }
+    check_grid(&rows, scale_size)?;
+    Ok(Triangles::from_parts(rows, scale_size, real_size, size_of_chunk))
+}
+
+/// checks that there are as many rows (with as many verticies in each) as a grid of `scale_size` has, so a bad file
+/// is an error instead of a panic later on
+fn check_grid(rows: &[Vec<F64x2>], scale_size: (u32, u32)) -> Result<()> {
+    let (width, height) = (scale_size.0 as usize, scale_size.1 as usize);
+    // rows 0 to `height`, and then the start of one more that is left over from generating the grid
+    ensure!(rows.len() == height + 2, "{} rows, but a grid {height} high has {}", rows.len(), height + 2);
+    for (y, row) in rows.iter().enumerate() {
+        // the even rows stick out half a triangle past the odd ones on either side, so have one more vertex.
+        // the left over row is empty after an even row, and has one vertex after an odd one
+        let expected = match y {
+            y if y > height => height % 2,
+            y if y % 2 == 0 => width + 2,
+            _ => width + 1,
+        };
+        ensure!(row.len() == expected, "row {y} has {} verticies, but should have {expected}", row.len());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonTriMesh {
+    format: String,
+    version: u32,
+    size_of_chunk: f64,
+    verticies: Vec<[f64; 2]>,
+    /// [`TriMesh::on_border`], under its old name
+    #[serde(rename = "fixed")]
+    on_border: Vec<bool>,
+    faces: Vec<[usize; 3]>,
+}
+
+pub fn write_trimesh_json(mesh: &TriMesh, writer: impl Write) -> Result<()> {
+    let json = JsonTriMesh {
+        format: TRIMESH_FORMAT_NAME.to_string(),
+        version: VERSION,
+        size_of_chunk: mesh.size_of_chunk(),
+        verticies: mesh.positions().iter().map(|v| [v.x, v.y]).collect(),
+        on_border: mesh.on_border().to_vec(),
+        faces: mesh.faces().to_vec(),
+    };
+    serde_json::to_writer(writer, &json)?;
+    Ok(())
+}
+
+pub fn read_trimesh_json(reader: impl Read) -> Result<TriMesh> {
+    let mesh: JsonTriMesh = serde_json::from_reader(reader)?;
+    ensure!(mesh.format == TRIMESH_FORMAT_NAME, "unknown format {:?}", mesh.format);
+    ensure!(mesh.version == VERSION, "unsupported version {}", mesh.version);
+    check_trimesh(mesh.verticies.len(), mesh.on_border.len(), &mesh.faces)?;
+    Ok(TriMesh::from_parts(
+        mesh.verticies.into_iter().map(F64x2::from).collect(),
+        mesh.on_border,
+        mesh.faces,
+        mesh.size_of_chunk,
+    ))
+}
+
+pub fn write_trimesh_binary(mesh: &TriMesh, mut writer: impl Write) -> Result<()> {
+    writer.write_all(TRIMESH_MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&mesh.size_of_chunk().to_le_bytes())?;
+    writer.write_all(&u32::try_from(mesh.vert_count())?.to_le_bytes())?;
+    for (vert, on_border) in mesh.positions().iter().zip(mesh.on_border()) {
+        writer.write_all(&vert.x.to_le_bytes())?;
+        writer.write_all(&vert.y.to_le_bytes())?;
+        writer.write_all(&[*on_border as u8])?;
+    }
+    writer.write_all(&u32::try_from(mesh.face_count())?.to_le_bytes())?;
+    for face in mesh.faces() {
+        for v in face {
+            writer.write_all(&u32::try_from(*v)?.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_trimesh_binary(mut reader: impl Read) -> Result<TriMesh> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != TRIMESH_MAGIC {
+        bail!("missing magic bytes");
+    }
+    let version = read_u32(&mut reader)?;
+    ensure!(version == VERSION, "unsupported version {version}");
+    let size_of_chunk = read_f64(&mut reader)?;
+    let num_verts = read_u32(&mut reader)?;
+    let mut verts = vec![];
+    let mut on_border = vec![];
+    for _ in 0..num_verts {
+        verts.push(F64x2::new(read_f64(&mut reader)?, read_f64(&mut reader)?));
+        let mut flag = [0u8];
+        reader.read_exact(&mut flag)?;
+        on_border.push(flag[0] != 0);
+    }
+    let num_faces = read_u32(&mut reader)?;
+    let mut faces = vec![];
+    for _ in 0..num_faces {
+        faces.push([
+            read_u32(&mut reader)? as usize,
+            read_u32(&mut reader)? as usize,
+            read_u32(&mut reader)? as usize,
+        ]);
+    }
+    check_trimesh(verts.len(), on_border.len(), &faces)?;
+    Ok(TriMesh::from_parts(verts, on_border, faces, size_of_chunk))
+}
+
+fn check_trimesh(num_verts: usize, num_flags: usize, faces: &[[usize; 3]]) -> Result<()> {
+    ensure!(num_verts == num_flags, "{num_verts} verticies, but {num_flags} border flags");
+    ensure!(
+        faces.iter().flatten().all(|v| *v < num_verts),
+        "a face refers to a vertex that does not exist"
+    );
+    // same as what TriMesh::from_parts checks, so a bad file is an error instead of a panic
+    let mut sides = HashSet::with_capacity(faces.len() * 3);
+    for face in faces {
+        for i in 0..3 {
+            let side = (face[i], face[(i + 1) % 3]);
+            ensure!(side.0 != side.1, "a face uses the same vertex twice");
+            ensure!(sides.insert(side), "two faces overlap along the same side");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a grid with verticies that do not land on round numbers, which all have to come back exactly
+    fn grid() -> Triangles {
+        let mut tris = Triangles::for_image(53, 31, 7.3);
+        for (i, v) in tris.verts().into_iter().enumerate() {
+            tris.set_vert(v, tris.vert(v) + F64x2::new(i as f64 / 3.0, -1e-13 * i as f64));
+        }
+        tris
+    }
+
+    fn round_trip<M: MeshFile>(mesh: &M, json: bool) -> Result<M> {
+        let mut bytes = vec![];
+        if json {
+            mesh.write_json(&mut bytes)?;
+            M::read_json(&mut bytes.as_slice())
+        } else {
+            mesh.write_binary(&mut bytes)?;
+            M::read_binary(&mut bytes.as_slice())
+        }
+    }
+
+    #[test]
+    fn grid_round_trips_exactly() {
+        for size in [7.3, 10.0, 15.0] {
+            let tris = Triangles::for_image(90, 61, size);
+            for json in [true, false] {
+                assert_eq!(round_trip(&tris, json).unwrap(), tris);
+            }
+        }
+        let tris = grid();
+        for json in [true, false] {
+            assert_eq!(round_trip(&tris, json).unwrap(), tris);
+        }
+    }
+
+    #[test]
+    fn trimesh_round_trips_exactly() {
+        let mesh = TriMesh::from(&grid());
+        for json in [true, false] {
+            assert_eq!(round_trip(&mesh, json).unwrap(), mesh);
+        }
+    }
+
+    #[test]
+    fn wrong_shape_grid_is_an_error() {
+        let tris = grid();
+        let mut missing_row = tris.rows().to_vec();
+        missing_row.pop();
+        let mut short_row = tris.rows().to_vec();
+        short_row[1].pop();
+        for rows in [missing_row, short_row] {
+            let bad = Triangles::from_parts(rows, tris.scale_size(), tris.real_size(), tris.size_of_chunk());
+            for json in [true, false] {
+                assert!(round_trip(&bad, json).is_err());
+            }
+        }
+    }
+}
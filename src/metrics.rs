@@ -0,0 +1,123 @@
+//! whole image quality metrics, for objectively comparing how well different settings reproduce an image
+
+use image::{Rgb, RgbImage};
+
+use crate::{
+    mesh::Mesh,
+    scoring::{raster, stats::ImageStats},
+};
+
+/// how close a rendered mesh is to the image it was fit to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// mean squared error per channel (0-255 scale)
+    pub mse: f64,
+    /// peak signal to noise ratio, in dB (higher is better, infinite for a perfect match)
+    pub psnr: f64,
+    /// mean structural similarity of the luma (1 is a perfect match)
+    pub ssim: f64,
+}
+
+impl Quality {
+    /// compares two images of the same size
+    pub fn between(a: &RgbImage, b: &RgbImage) -> Self {
+        let mse = mse(a, b);
+        Self {
+            mse,
+            psnr: psnr(mse),
+            ssim: ssim(a, b),
+        }
+    }
+
+    /// renders the mesh (with colors from `fill`) and compares it to `image`
+    pub fn measure(tris: &impl Mesh, fill: &ImageStats, image: &RgbImage) -> Self {
+        Self::between(image, &render_flat(tris, fill))
+    }
+}
+
+/// draws every triangle filled with its average color, with no anti-aliasing.
+///
+/// this is much faster than [`io::render_image`](crate::io::render_image), and covers exactly the pixels the scoring sees
+pub fn render_flat(tris: &impl Mesh, fill: &ImageStats) -> RgbImage {
+    let (width, height) = (fill.width(), fill.height());
+    let mut out = RgbImage::new(width, height);
+    for tri in tris.triangles() {
+        let Some(color) = fill.color(tri) else {
+            continue;
+        };
+        for (x, y) in raster::pixels(tri, width, height) {
+            out.put_pixel(x, y, color);
+        }
+    }
+    out
+}
+
+/// mean squared error over every channel of every pixel
+pub fn mse(a: &RgbImage, b: &RgbImage) -> f64 {
+    assert_eq!(a.dimensions(), b.dimensions());
+    let total = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum::<f64>();
+    total / a.as_raw().len().max(1) as f64
+}
+
+/// PSNR (in dB) for a given mean squared error of 8 bit values
+pub fn psnr(mse: f64) -> f64 {
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// size of the windows SSIM is measured over
+const SSIM_WINDOW: u32 = 8;
+
+/// mean SSIM of the luma of two images, over every 8x8 window
+pub fn ssim(a: &RgbImage, b: &RgbImage) -> f64 {
+    assert_eq!(a.dimensions(), b.dimensions());
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (width, height) = a.dimensions();
+    let win = SSIM_WINDOW.min(width).min(height);
+    if win == 0 {
+        return 1.0;
+    }
+
+    // summed area tables of a, b, a^2, b^2 and ab, so each window is only a few lookups
+    let luma = |Rgb([r, g, b]): Rgb<u8>| 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+    let stride = width as usize + 1;
+    let mut tables = vec![[0.0f64; 5]; stride * (height as usize + 1)];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let (la, lb) = (luma(*a.get_pixel(x as u32, y as u32)), luma(*b.get_pixel(x as u32, y as u32)));
+            let values = [la, lb, la * la, lb * lb, la * lb];
+            let (up, left, up_left) = (tables[y * stride + x + 1], tables[(y + 1) * stride + x], tables[y * stride + x]);
+            let entry = &mut tables[(y + 1) * stride + x + 1];
+            for i in 0..5 {
+                entry[i] = values[i] + up[i] + left[i] - up_left[i];
+            }
+        }
+    }
+
+    let n = (win * win) as f64;
+    let mut total = 0.0;
+    let mut count = 0usize;
+    for y in 0..=(height - win) as usize {
+        for x in 0..=(width - win) as usize {
+            let (x1, y1) = (x + win as usize, y + win as usize);
+            let mut sums = [0.0; 5];
+            for (i, sum) in sums.iter_mut().enumerate() {
+                *sum = tables[y1 * stride + x1][i] - tables[y * stride + x1][i] - tables[y1 * stride + x][i]
+                    + tables[y * stride + x][i];
+            }
+            let (mean_a, mean_b) = (sums[0] / n, sums[1] / n);
+            let var_a = sums[2] / n - mean_a * mean_a;
+            let var_b = sums[3] / n - mean_b * mean_b;
+            let cov = sums[4] / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            count += 1;
+        }
+    }
+    total / count as f64
+}
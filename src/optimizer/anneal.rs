@@ -0,0 +1,84 @@
+use clap::ValueEnum;
+use rand::{prelude::SliceRandom, Rng, RngCore};
+
+use super::{MoveInfo, Optimizer};
+use crate::vec2::F64x2;
+
+/// how the temperature of [`Anneal`] goes down over the iterations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum Cooling {
+    /// multiplied by [`Anneal::cooling_rate`] every iteration
+    #[default]
+    Exponential,
+    /// straight down to zero at the last iteration
+    Linear,
+}
+
+/// simulated annealing.
+///
+/// each vertex moves to the best of [`MoveInfo::candidates`] if it is better than where it is now, like [`Greedy`](super::Greedy).
+/// if none of them are, a random one is taken anyway with a chance of `e^(-worse / temperature)`, where `worse` is how much
+/// worse it scores, as a fraction of the score where the vertex is now. so while it is hot the mesh can get out of local
+/// optima, and as it cools down it settles into one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anneal {
+    /// temperature at the first iteration
+    pub temperature: f64,
+    pub cooling: Cooling,
+    /// what the temperature is multiplied by every iteration, for [`Cooling::Exponential`]
+    pub cooling_rate: f64,
+}
+
+impl Default for Anneal {
+    fn default() -> Self {
+        Self {
+            temperature: 0.02,
+            cooling: Cooling::default(),
+            cooling_rate: 0.9,
+        }
+    }
+}
+
+impl Anneal {
+    pub fn new(temperature: f64) -> Self {
+        Self {
+            temperature,
+            ..Self::default()
+        }
+    }
+
+    pub fn cooling(mut self, cooling: Cooling) -> Self {
+        self.cooling = cooling;
+        self
+    }
+
+    pub fn cooling_rate(mut self, cooling_rate: f64) -> Self {
+        self.cooling_rate = cooling_rate;
+        self
+    }
+
+    /// the temperature during `iteration` (starting at 0) out of `iterations`
+    pub fn temperature_at(&self, iteration: usize, iterations: usize) -> f64 {
+        match self.cooling {
+            Cooling::Exponential => self.temperature * self.cooling_rate.powi(iteration as i32),
+            Cooling::Linear => self.temperature * (1.0 - iteration as f64 / iterations.max(1) as f64).max(0.0),
+        }
+    }
+}
+
+impl Optimizer for Anneal {
+    fn pick(&self, info: &MoveInfo, rng: &mut dyn RngCore) -> Option<F64x2> {
+        let candidates = info.candidates(rng);
+        let (best, best_score) = candidates.iter().copied().max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        if best_score > info.original_score {
+            return Some(best);
+        }
+        let temperature = self.temperature_at(info.iteration, info.cfg.iterations);
+        if temperature <= 0.0 {
+            return None;
+        }
+        let (new, score) = *candidates.choose(rng)?;
+        let worse = (info.original_score - score) / info.original_score.abs().max(f64::EPSILON);
+        rng.gen_bool((-worse / temperature).exp().min(1.0)).then_some(new)
+    }
+}
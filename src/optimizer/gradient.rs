@@ -0,0 +1,124 @@
+use rand::RngCore;
+
+use super::{Greedy, MoveInfo, Optimizer};
+use crate::vec2::F64x2;
+
+/// how far (in [`MoveInfo::shift`]s) either side of a vertex the score is checked to
+/// estimate the gradient. the score is bumpy at the scale of single pixels, so this smooths it out a bit
+const SPACING: f64 = 2.0;
+
+/// moves each vertex uphill, in the direction the score around it gets better fastest.
+///
+/// the direction (the gradient of the score with respect to the position of the vertex) is estimated with finite
+/// differences, scoring the vertex a little way either side of where it is along each axis. it then tries moving
+/// [`FitConfig::steps`](crate::FitConfig::steps) shifts that way, and if that is better keeps doubling the step while
+/// it gets better still (up to `doublings` times), or if it is not halves it untill it is (up to `halvings` times).
+/// so verticies far from where they should be get there in a few big steps, without having to score every move
+/// that [`Greedy`] does every time.
+///
+/// close to an optimum the estimate gets unreliable, so if no step along it is better the vertex falls back to the
+/// moves [`Greedy`] would try (unless `fallback` is off). this works best with scorers that change smoothly as the
+/// verticies move, like the reconstruction ones (especially with [`SamplingMode::Coverage`](crate::SamplingMode::Coverage))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gradient {
+    /// how many times the step can be halved before giving up on it
+    pub halvings: u32,
+    /// how many times the step can be doubled while it keeps getting better
+    pub doublings: u32,
+    /// try the [`Greedy`] moves when following the gradient does not help
+    pub fallback: bool,
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self {
+            halvings: 5,
+            doublings: 3,
+            fallback: true,
+        }
+    }
+}
+
+impl Gradient {
+    pub fn halvings(mut self, halvings: u32) -> Self {
+        self.halvings = halvings;
+        self
+    }
+
+    pub fn doublings(mut self, doublings: u32) -> Self {
+        self.doublings = doublings;
+        self
+    }
+
+    pub fn fallback(mut self, fallback: bool) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// the (estimated) gradient of the score around the vertex, only along `info.along` if it is set
+    pub fn gradient(info: &MoveInfo) -> F64x2 {
+        let h = info.shift * SPACING;
+        let axes = match info.along {
+            Some(dir) => vec![dir],
+            None => vec![F64x2::new(1.0, 0.0), F64x2::new(0.0, 1.0)],
+        };
+        axes.into_iter().fold(F64x2::zero(), |gradient, axis| {
+            let ahead = info.score_at(info.original + axis * h);
+            let behind = info.score_at(info.original - axis * h);
+            // use a one sided difference if the vertex can not move one way
+            let slope = match (ahead, behind) {
+                (Some(ahead), Some(behind)) => (ahead - behind) / (2.0 * h),
+                (Some(ahead), None) => (ahead - info.original_score) / h,
+                (None, Some(behind)) => (info.original_score - behind) / h,
+                (None, None) => 0.0,
+            };
+            gradient + axis * slope
+        })
+    }
+
+    /// the best point found along the gradient, if any of them are better than where the vertex is now
+    fn line_search(&self, info: &MoveInfo) -> Option<F64x2> {
+        let gradient = Self::gradient(info);
+        let length = gradient.length();
+        // flat (or NaN), nowhere to go
+        if length == 0.0 || length.is_nan() {
+            return None;
+        }
+        let dir = gradient / length;
+        let better = |step: f64| {
+            let new = info.original + dir * step;
+            info.score_at(new)
+                .filter(|score| *score > info.original_score)
+                .map(|score| (new, score))
+        };
+        let mut step = info.shift * info.cfg.steps as f64;
+        let mut halved = 0;
+        let (mut best, mut best_score) = loop {
+            match better(step) {
+                Some(found) => break found,
+                None if halved < self.halvings => (step, halved) = (step / 2.0, halved + 1),
+                None => return None,
+            }
+        };
+        // if it had to be halved, twice the step was already not better
+        let doublings = if halved == 0 { self.doublings } else { 0 };
+        for _ in 0..doublings {
+            step *= 2.0;
+            match better(step) {
+                Some((new, score)) if score > best_score => (best, best_score) = (new, score),
+                _ => break,
+            }
+        }
+        Some(best)
+    }
+}
+
+impl Optimizer for Gradient {
+    fn pick(&self, info: &MoveInfo, rng: &mut dyn RngCore) -> Option<F64x2> {
+        match self.line_search(info) {
+            Some(new) => Some(new),
+            None if self.fallback => Greedy.pick(info, rng),
+            None => None,
+        }
+    }
+}
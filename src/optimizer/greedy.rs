@@ -0,0 +1,28 @@
+use rand::{prelude::SliceRandom, Rng, RngCore};
+
+use super::{MoveInfo, Optimizer};
+use crate::vec2::F64x2;
+
+/// moves each vertex to the best scoring of [`MoveInfo::candidates`], as long as it is better than where it is now.
+///
+/// with [`FitConfig::randomness`](crate::FitConfig::randomness) set a random candidate is sometimes picked instead,
+/// but it is still only taken if it is better.
+///
+/// this is the hill climber trifit has always used, and on the grid it gives the same results as it always has.
+/// on any other mesh, moves that would turn a triangle inside out are skipped (see [`MoveInfo::keep_winding`])
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Greedy;
+
+impl Optimizer for Greedy {
+    fn pick(&self, info: &MoveInfo, rng: &mut dyn RngCore) -> Option<F64x2> {
+        let randomness = info.cfg.randomness;
+        let scores = info.candidates(rng);
+        let best = scores.iter().max_by(|(_, a), (_, b)| a.total_cmp(b)); // larger scores are considered better
+
+        let (mut new, mut best_score) = best.copied()?;
+        if randomness != 0 && rng.gen_bool(1.0 / randomness as f64) {
+            (new, best_score) = *scores.choose(rng).unwrap();
+        }
+        (best_score > info.original_score).then_some(new)
+    }
+}
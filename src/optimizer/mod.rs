@@ -0,0 +1,108 @@
+//! how verticies are moved around while fitting. see [`Optimizer`]
+
+pub mod anneal;
+pub mod gradient;
+pub mod greedy;
+pub mod search;
+
+use std::{fmt::Debug, sync::Arc};
+
+use clap::ValueEnum;
+use rand::RngCore;
+
+use crate::{
+    fit::FitConfig,
+    scoring::{point_in_triangle, score_for_group, stats::ImageStats},
+    triangle::Triangle,
+    vec2::F64x2,
+};
+pub use anneal::{Anneal, Cooling};
+pub use gradient::Gradient;
+pub use greedy::Greedy;
+pub use search::{Growth, SearchPattern, Shifts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum OptimizerKind {
+    /// moves each vertex to the best nearby position, if it is better than where it is now
+    #[default]
+    Greedy,
+    /// simulated annealing: like greedy, but sometimes takes a worse move (less and less often as it cools down),
+    /// so it can get out of local optima
+    Anneal,
+    /// moves each vertex the way the score gets better fastest, by however far is better
+    Gradient,
+}
+
+impl OptimizerKind {
+    /// the optimizer that implements this, with its default settings
+    pub fn optimizer(self) -> Arc<dyn Optimizer> {
+        match self {
+            OptimizerKind::Greedy => Arc::new(Greedy),
+            OptimizerKind::Anneal => Arc::new(Anneal::default()),
+            OptimizerKind::Gradient => Arc::new(Gradient::default()),
+        }
+    }
+}
+
+/// decides where each vertex is moved to.
+///
+/// the built in optimizers are [`Greedy`], [`Anneal`] and [`Gradient`], and any other implementation can be used by passing it
+/// to [`FitConfig::optimizer`]
+pub trait Optimizer: Debug + Send + Sync {
+    /// where to move the vertex described by `info`, or `None` to leave it where it is
+    fn pick(&self, info: &MoveInfo, rng: &mut dyn RngCore) -> Option<F64x2>;
+}
+
+/// everything an [`Optimizer`] gets to look at when moving a vertex
+#[derive(Debug, Clone, Copy)]
+pub struct MoveInfo<'a> {
+    /// color stats of the image being fit to
+    pub stats: &'a ImageStats,
+    pub cfg: &'a FitConfig,
+    /// the triangles around the vertex, with it as their first corner
+    pub group: &'a [Triangle],
+    /// where the vertex is now
+    pub original: F64x2,
+    /// [`Scorer::value`](crate::Scorer::value) of the triangles around the vertex where it is now
+    pub original_score: f64,
+    /// if set, the vertex can only move in this direction (or the opposite one), see
+    /// [`Movement::Along`](crate::mesh::Movement::Along)
+    pub along: Option<F64x2>,
+    /// if set, the vertex can not move anywhere that would turn one of its triangles inside out, folding the mesh
+    /// over itself. this is set for every mesh but the grid (see [`Mesh::keeps_winding`](crate::mesh::Mesh::keeps_winding))
+    pub keep_winding: bool,
+    /// the iteration being run (starting at 0)
+    pub iteration: usize,
+    /// how far to move the vertex in each step. this is [`FitConfig::shift`], unless the search pattern is
+    /// [`adaptive`](SearchPattern::adaptive)
+    pub shift: f64,
+}
+
+impl MoveInfo<'_> {
+    /// [`Scorer::value`](crate::Scorer::value) of the triangles around the vertex if it was at `pos`,
+    /// or `None` if it can not move there (outside of its triangles, or turning one inside out if
+    /// [`MoveInfo::keep_winding`] is set)
+    pub fn score_at(&self, pos: F64x2) -> Option<f64> {
+        if !self.group.iter().any(|t| point_in_triangle(pos, t.0, t.1, t.2)) {
+            return None;
+        }
+        if self.keep_winding && !self.group.iter().all(|t| Triangle(pos, t.1, t.2).signed_area() > 0.0) {
+            return None;
+        }
+        let moved = self.group.iter().map(|t| Triangle(pos, t.1, t.2)).collect::<Vec<_>>();
+        let scorer = &*self.cfg.scorer;
+        Some(scorer.value(&score_for_group(self.stats, &moved, self.cfg.tri_size, scorer)))
+    }
+
+    /// nearby positions to try (from [`FitConfig::search`]), along with the score there, for the ones the vertex
+    /// can move to
+    pub fn candidates(&self, rng: &mut dyn RngCore) -> Vec<(F64x2, f64)> {
+        self.cfg
+            .search
+            .offsets(self.shift, self.cfg.steps, self.along, rng)
+            .into_iter()
+            .map(|offset| self.original + offset)
+            .filter_map(|new| self.score_at(new).map(|score| (new, score)))
+            .collect()
+    }
+}
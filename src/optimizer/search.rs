@@ -0,0 +1,145 @@
+use std::{collections::HashMap, f64::consts::TAU};
+
+use clap::ValueEnum;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{fit::FitConfig, vec2::F64x2};
+
+/// how far apart the steps in each direction get
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum Growth {
+    /// 1, 2, 3, 4... shifts
+    #[default]
+    Linear,
+    /// 1, 2, 4, 8... shifts, to reach further with the same number of steps
+    Geometric,
+}
+
+/// the moves that are tried for each vertex (see [`MoveInfo::candidates`](super::MoveInfo::candidates)):
+/// rings of steps going out in evenly spaced directions, and optionally some random ones as well
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchPattern {
+    /// number of directions to step in, spread evenly around a square (so 8 is the 4 sides and 4 diagonals,
+    /// and 16 adds the ones half way between them)
+    pub directions: u32,
+    pub growth: Growth,
+    /// number of extra moves to random places within the outer ring
+    pub random: u32,
+    /// keep a separate shift for each vertex, which is halved every time it can not find a better position
+    /// (and doubled back when it does)
+    pub adaptive: bool,
+}
+
+impl Default for SearchPattern {
+    fn default() -> Self {
+        Self {
+            directions: 8,
+            growth: Growth::default(),
+            random: 0,
+            adaptive: false,
+        }
+    }
+}
+
+impl SearchPattern {
+    /// panics if `directions` is 0, since then there would be nowhere to move to
+    pub fn directions(mut self, directions: u32) -> Self {
+        assert!(directions > 0, "there has to be at least one direction to step in");
+        self.directions = directions;
+        self
+    }
+
+    pub fn growth(mut self, growth: Growth) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    pub fn random(mut self, random: u32) -> Self {
+        self.random = random;
+        self
+    }
+
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// how many shifts out step `i` (starting at 1) is
+    fn distance(&self, i: u32) -> f64 {
+        match self.growth {
+            Growth::Linear => i as f64,
+            Growth::Geometric => 2f64.powi(i as i32 - 1),
+        }
+    }
+
+    /// every offset to try moving a vertex by, `steps` steps of `shift` out in each direction (only forwards and
+    /// backwards if it can only move `along` a line)
+    pub fn offsets(&self, shift: f64, steps: u32, along: Option<F64x2>, rng: &mut dyn RngCore) -> Vec<F64x2> {
+        let directions = match along {
+            Some(dir) => vec![dir, -dir],
+            None => (0..self.directions)
+                .map(|i| on_square(8.0 * i as f64 / self.directions as f64))
+                .collect(),
+        };
+        let mut offsets = directions
+            .into_iter()
+            .flat_map(|dir| (1..=steps).map(move |i| dir * shift * self.distance(i)))
+            .collect::<Vec<_>>();
+        let reach = shift * self.distance(steps);
+        offsets.extend((0..self.random).map(|_| match along {
+            Some(dir) => dir * rng.gen_range(-reach..=reach),
+            None => {
+                // evenly spread over the circle
+                let (r, angle) = (reach * rng.gen::<f64>().sqrt(), rng.gen::<f64>() * TAU);
+                F64x2::new(r * angle.cos(), r * angle.sin())
+            }
+        }));
+        offsets
+    }
+}
+
+/// the point `t` of the way clockwise around the edge of the square from -1 to 1, starting straight up (so the
+/// corners are whole numbers of the way around, out of 8)
+fn on_square(t: f64) -> F64x2 {
+    match t {
+        t if t < 1.0 => F64x2::new(t, 1.0),
+        t if t < 3.0 => F64x2::new(1.0, 2.0 - t),
+        t if t < 5.0 => F64x2::new(4.0 - t, -1.0),
+        t if t < 7.0 => F64x2::new(-1.0, t - 6.0),
+        t => F64x2::new(t - 8.0, 1.0),
+    }
+}
+
+/// how many times the shift of a vertex can be halved before it goes back to the full shift (in case things around it
+/// have changed enough for a big move to be better again)
+const MAX_HALVINGS: u32 = 4;
+
+/// the shift each vertex is at, for an [`adaptive`](SearchPattern::adaptive) search pattern
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Shifts {
+    /// how many times the shift has been halved, by [`Mesh::vert_key`](crate::Mesh::vert_key)
+    halvings: HashMap<u64, u32>,
+}
+
+impl Shifts {
+    /// the shift for the vertex with `key`
+    pub fn get(&self, cfg: &FitConfig, key: u64) -> f64 {
+        match cfg.search.adaptive {
+            true => cfg.shift / 2f64.powi(self.halvings.get(&key).copied().unwrap_or(0) as i32),
+            false => cfg.shift,
+        }
+    }
+
+    /// halves the shift of a vertex if it did not move, or doubles it back if it did
+    pub fn update(&mut self, cfg: &FitConfig, key: u64, moved: bool) {
+        if !cfg.search.adaptive {
+            return;
+        }
+        let halvings = self.halvings.entry(key).or_default();
+        *halvings = match moved {
+            true => halvings.saturating_sub(1),
+            false => (*halvings + 1) % (MAX_HALVINGS + 1),
+        };
+    }
+}
@@ -1,101 +1,81 @@
-use std::cmp::Ordering;
-
-use image::Rgb;
-
-use super::{ScoreInfo, get_color_in_triangle, min, max, average};
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Score {
-    /// has no info (eg: a triangle with no pixels covered)
-    is_none: bool,
-    /// distance between colors, 0-100 (0-perfect match, 100=perfectly disarrayed (impossible?))
-    average_color_distance: f64,
-    /// range of 0-100 (0=line, 100=square)
-    squareness: f64,
-}
-
-impl Score {
-    pub fn none() -> Self {
-        Self { is_none: true, average_color_distance: 0.0, squareness: 0.0 }
+use super::{max, min, Score, ScoreInfo, Scorer};
+use crate::triangle::Triangle;
+
+/// average based, weighted against very thin triangles
+///
+/// scores have three terms:
+/// - `1` if the triangle covers any pixels, and `0` if it has no info (this becomes the fraction of triangles with info when combined)
+/// - distance between colors and their average, 0-100 (0-perfect match, 100=perfectly disarrayed (impossible?))
+/// - squareness, range of 0-100 (0=line, 100=square)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AvgWithShapeWeight;
+
+/// like [`AvgWithShapeWeight`], but uses the root mean square distance between the colors and their average, which
+/// can be found from the variance of the colors instead of looking at every pixel, so is much faster.
+/// it is never smaller than the mean distance, and equal to it when every pixel is the same distance away
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FastAvgWithShapeWeight;
+
+impl AvgWithShapeWeight {
+    /// score for a triangle with no info (eg: a triangle with no pixels covered)
+    pub fn none() -> Score {
+        Score::default()
     }
-}
-
-impl super::Score for Score {
-    fn score_for<'a>(info: super::ScoreInfo<'a>) -> Self {
-        let ScoreInfo { triangle, image, tri_size: _ } = info;
-        let colors = get_color_in_triangle(image, triangle);
-
-        if colors.is_empty() {
-            return Score::none();
-        }
-
-        let minx = min(min(triangle.0.x, triangle.1.x), triangle.2.x);
-        let maxx = max(max(triangle.0.x, triangle.1.x), triangle.2.x);
-        let miny = min(min(triangle.0.y, triangle.1.y), triangle.2.y);
-        let maxy = max(max(triangle.0.y, triangle.1.y), triangle.2.y);
-        let width = maxx - minx;
-        let height = maxy - miny;
-
-        fn color_dist(a: Rgb<u8>, b: Rgb<u8>) -> f64 {
-            let r = a.0[0] as f64 - b.0[0] as f64;
-            let g = a.0[1] as f64 - b.0[1] as f64;
-            let b = a.0[2] as f64 - b.0[2] as f64;
-            (r.powi(2) + g.powi(2) + b.powi(2)).sqrt()
-        }
 
-        let avg = average(&colors);
-        let average_color_distance = colors
-            .iter()
-            .map(|color| color_dist(*color, avg))
-            .sum::<f64>()
-            / colors.len() as f64
-            / 25.5; /* scale into range of 0..100 */
-        if !(0.0 <= average_color_distance && average_color_distance <= 100.0) {
+    /// score for a (non empty) triangle, with the color distance (0-100) worked out some other way
+    pub fn with_distance(triangle: Triangle, average_color_distance: f64) -> Score {
+        if !(0.0..=100.0).contains(&average_color_distance) {
             warn!("average color distance out of range! (value: {average_color_distance})");
         }
 
-        let squareness = {
-            let m = max(width, height);
-            2.0 * ((((100.0 * (width + height)) / m) / 2.0) - 50.0)
-        };
-        if !(0.0 <= squareness && squareness <= 100.0) {
+        let squareness = squareness(triangle);
+        if !(0.0..=100.0).contains(&squareness) {
             warn!("squareness out of range! (value: {squareness})");
         }
 
-        Score {
-            is_none: false,
-            average_color_distance,
-            squareness,
-        }
+        Score::new(&[1.0, average_color_distance, squareness])
     }
+}
+
+/// how square the bounding box of a triangle is, 0-100 (0=line, 100=square)
+pub fn squareness(triangle: Triangle) -> f64 {
+    let minx = min(min(triangle.0.x, triangle.1.x), triangle.2.x);
+    let maxx = max(max(triangle.0.x, triangle.1.x), triangle.2.x);
+    let miny = min(min(triangle.0.y, triangle.1.y), triangle.2.y);
+    let maxy = max(max(triangle.0.y, triangle.1.y), triangle.2.y);
+    let width = maxx - minx;
+    let height = maxy - miny;
+
+    let m = max(width, height);
+    2.0 * ((((100.0 * (width + height)) / m) / 2.0) - 50.0)
+}
 
-    fn average(scores: &[Self]) -> Self {
-        if scores.is_empty() {
+impl Scorer for AvgWithShapeWeight {
+    fn score(&self, info: ScoreInfo) -> Score {
+        let ScoreInfo { triangle, stats: image, tri_size: _ } = info;
+        let stats = image.triangle(triangle);
+
+        if stats.is_empty() {
             return Self::none();
         }
-        Self {
-            is_none: false,
-            average_color_distance: scores
-                .iter()
-                .filter(|score| !score.is_none)
-                .map(|score| score.average_color_distance)
-                .sum::<f64>()
-                / scores.len() as f64,
-            squareness: scores.iter()
-                .filter(|score| !score.is_none)
-                .map(|score| score.squareness)
-                .sum::<f64>()
-                / scores.len() as f64,
+
+        fn color_dist(a: [f64; 3], b: [f64; 3]) -> f64 {
+            ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
         }
-    }
 
-    fn cmp(&self, rhs: &Score) -> Ordering {
-        self.score_value().partial_cmp(&rhs.score_value()).unwrap()
+        // the average is rounded down, like the colors themselves
+        let avg = stats.mean().map(f64::floor);
+        let mut total = 0.0;
+        image.for_each_pixel(triangle, |color, weight| total += color_dist(color, avg) * weight);
+        let average_color_distance = total / stats.count
+            / 25.5; /* scale into range of 0..100 */
+        Self::with_distance(triangle, average_color_distance)
     }
 
     /// 0..100, 0=worst, 100=best
-    fn score_value(&self) -> f64 {
-        if self.is_none {
+    fn value(&self, score: &Score) -> f64 {
+        let &[has_info, average_color_distance, squareness, ..] = score.terms();
+        if has_info <= 0.0 {
             return 0.0;
         }
         /*
@@ -103,13 +83,13 @@ impl super::Score for Score {
         and then start to care a LOT after that
         */
         // old version, much more simple (also more agressive at the start / lower at the end)
-        // let weighted_squareness = 1.0 - (3.0 * ((self.squareness / 100.0) - 1.0)).exp(); // 1-e^{3(x-1)}
+        // let weighted_squareness = 1.0 - (3.0 * ((squareness / 100.0) - 1.0)).exp(); // 1-e^{3(x-1)}
 
         // new version, more complex (does not get smaller as early, and does not end at zero)
         //
         // convert so 100=line, and 0=square (so that for good values (smaller) it gives more controll to the color part)
         // then scales into the 0.1 range
-        let s = (100.0 - self.squareness) / 100.0;
+        let s = (100.0 - squareness) / 100.0;
         let weighted_squareness = (1.528
             * ((1.0 - (3.0 * (0.9 * s - 1.0)).exp())
                 * (0.5 + (1.0 / (1.0 + (-s + 0.5).exp())) / 2.0)))
@@ -121,6 +101,27 @@ impl super::Score for Score {
 
         also converts from 100=worst, 0=best to 100=best, 0=worst for the color dist
         */
-        (100.0 - self.average_color_distance) * weighted_squareness
+        (100.0 - average_color_distance) * weighted_squareness
     }
-}
\ No newline at end of file
+}
+
+impl Scorer for FastAvgWithShapeWeight {
+    fn score(&self, info: ScoreInfo) -> Score {
+        let ScoreInfo { triangle, stats, tri_size: _ } = info;
+        let stats = stats.triangle(triangle);
+
+        if stats.is_empty() {
+            return AvgWithShapeWeight::none();
+        }
+
+        // the mean squared distance from the average color is just the sum of the variances of each channel
+        let average_color_distance = stats.variance().iter().sum::<f64>().sqrt()
+            / 25.5; /* scale into range of 0..100 */
+        AvgWithShapeWeight::with_distance(triangle, average_color_distance)
+    }
+
+    /// 0..100, 0=worst, 100=best
+    fn value(&self, score: &Score) -> f64 {
+        AvgWithShapeWeight.value(score)
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::OnceLock;
+
+use super::Score;
+use crate::mesh::Mesh;
+
+/// scores of individual triangles, indexed by the face indices from [`Mesh::faces_around`].
+///
+/// a triangles score only changes when one of its corners moves, so entries only need to be
+/// invalidated for the triangles around a moved vertex.
+/// filling in entries only needs a shared reference, so one cache can be used from multiple threads.
+#[derive(Debug, Default)]
+pub struct ScoreCache {
+    scores: Vec<OnceLock<Score>>,
+}
+
+impl ScoreCache {
+    pub fn new(triangle_count: usize) -> Self {
+        Self {
+            scores: (0..triangle_count).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    /// gets the cached score for a triangle, calling `score` to fill it in if it is not there
+    pub fn get_or_score(&self, index: usize, score: impl FnOnce() -> Score) -> &Score {
+        self.scores[index].get_or_init(score)
+    }
+
+    pub fn invalidate(&mut self, index: usize) {
+        self.scores[index].take();
+    }
+
+    /// invalidates every triangle with `v` as one of its corners
+    pub fn vertex_moved<M: Mesh>(&mut self, tris: &M, v: M::Vert) {
+        for (index, _) in tris.faces_around(v) {
+            self.invalidate(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+    use crate::{
+        scoring::{score, stats::ImageStats, ScoringScheme},
+        triangle::Triangles,
+        vec2::F64x2,
+    };
+
+    #[test]
+    fn moving_a_vertex_rescores_its_triangles() {
+        let image = RgbImage::from_fn(60, 40, |x, y| Rgb([(x * 4) as u8, (y * 6) as u8, ((x + y) * 2) as u8]));
+        let stats = ImageStats::new(&image);
+        let scorer = ScoringScheme::AvgWithShapeWeight.scorer();
+        let mut tris = Triangles::for_image(image.width(), image.height(), 10.0);
+        let mut cache = ScoreCache::new(tris.face_index_bound());
+        let scored = Cell::new(0);
+        let fill = |tris: &Triangles, cache: &ScoreCache| {
+            for v in tris.verts() {
+                for (i, t) in tris.faces_around(v) {
+                    let cached = *cache.get_or_score(i, || {
+                        scored.set(scored.get() + 1);
+                        score(t, &stats, 10.0, &*scorer)
+                    });
+                    // whatever is cached has to be the score of the triangle as it is now
+                    assert_eq!(cached, score(t, &stats, 10.0, &*scorer));
+                }
+            }
+        };
+        fill(&tris, &cache);
+        assert_eq!(scored.get(), tris.triangles().len());
+
+        let v = (3, 2);
+        let around = tris.faces_around(v);
+        assert!(!around.is_empty());
+        tris.set_vert(v, tris.vert(v) + F64x2::new(2.5, -1.5));
+        cache.vertex_moved(&tris, v);
+        scored.set(0);
+        fill(&tris, &cache);
+        assert_eq!(scored.get(), around.len());
+    }
+}
@@ -0,0 +1,56 @@
+use image::Rgb;
+use palette::{FromColor, Lab, LinSrgb, Oklab, Srgb};
+
+/// color space that [`ImageStats`](super::stats::ImageStats) sums pixels in.
+///
+/// averages and variances are taken in this space, so it changes both what "average color" means
+/// and how far apart two colors are.
+/// every space is scaled so a channel ranges over roughly 0..100 or more, which keeps the scoring schemes happy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// gamma encoded sRGB, 0-255 (the raw pixel values)
+    #[default]
+    Srgb,
+    /// linear light sRGB, 0-255. averages here are what you would see by blurring the image
+    LinearRgb,
+    /// OKLab, with every component times 100 (so L is 0-100)
+    Oklab,
+    /// CIELAB (D65 white point), distances in which are the classic ΔE*76
+    Cielab,
+}
+
+impl ColorSpace {
+    /// converts a pixel into this space
+    pub fn encode(self, Rgb(px): Rgb<u8>) -> [f64; 3] {
+        let srgb = Srgb::new(px[0], px[1], px[2]).into_format::<f64>();
+        match self {
+            ColorSpace::Srgb => px.map(f64::from),
+            ColorSpace::LinearRgb => {
+                let lin = srgb.into_linear();
+                [lin.red * 255.0, lin.green * 255.0, lin.blue * 255.0]
+            }
+            ColorSpace::Oklab => {
+                let lab = Oklab::from_color(srgb.into_linear());
+                [lab.l * 100.0, lab.a * 100.0, lab.b * 100.0]
+            }
+            ColorSpace::Cielab => {
+                let lab = Lab::from_color(srgb.into_linear());
+                [lab.l, lab.a, lab.b]
+            }
+        }
+    }
+
+    /// converts a color in this space back into a pixel, clamping anything outside of sRGB.
+    /// sRGB colors are rounded down, like averages of the raw pixel values always have been
+    pub fn decode(self, c: [f64; 3]) -> Rgb<u8> {
+        let lin = match self {
+            ColorSpace::Srgb => return Rgb(c.map(|c| c.floor().clamp(0.0, 255.0) as u8)),
+            ColorSpace::LinearRgb => LinSrgb::new(c[0] / 255.0, c[1] / 255.0, c[2] / 255.0),
+            ColorSpace::Oklab => LinSrgb::from_color(Oklab::new(c[0] / 100.0, c[1] / 100.0, c[2] / 100.0)),
+            ColorSpace::Cielab => LinSrgb::from_color(Lab::new(c[0], c[1], c[2])),
+        };
+        let lin = LinSrgb::new(lin.red.clamp(0.0, 1.0), lin.green.clamp(0.0, 1.0), lin.blue.clamp(0.0, 1.0));
+        let srgb = Srgb::from_linear(lin);
+        Rgb([srgb.red, srgb.green, srgb.blue].map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8))
+    }
+}
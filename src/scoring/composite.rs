@@ -0,0 +1,178 @@
+//! a scorer built out of a weighted sum of simple terms, so the look can be tuned without writing a new scheme.
+//!
+//! every term is a penalty that is (roughly) 0 for a perfect triangle and 1 for a very bad one, and the
+//! score of a group is the weighted sum of the average of each term.
+//!
+//! weights can be loaded from a JSON file like this (any terms left out are not used):
+//!
+//! ```json
+//! {
+//!   "weights": { "color-variance": 1.0, "min-angle": 0.05 },
+//!   "percentile": 0.05
+//! }
+//! ```
+
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::{avg_with_shape_weight::squareness, Score, ScoreInfo, Scorer};
+
+/// one part of a [`Composite`] score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Term {
+    /// root mean square distance of the colors from their average
+    ColorVariance,
+    /// how far from the average color the worst `percentile` of the pixels are (in the channel that varies the most)
+    PercentileDeviation,
+    /// how far the bounding box of the triangle is from being square
+    Squareness,
+    /// how far the smallest angle is from 60°, which punishes thin slivers
+    MinAngle,
+    /// how far the area is from that of an equilateral triangle with sides of `tri_size`
+    AreaDeviation,
+    /// how much the sides of the triangle cut across edges in the image, rather than following them
+    /// (0 is right along strong edges, 0.5 is no edges, and 1 is straight across strong edges)
+    EdgeAlignment,
+}
+
+impl Term {
+    pub const ALL: [Term; 6] = [
+        Term::ColorVariance,
+        Term::PercentileDeviation,
+        Term::Squareness,
+        Term::MinAngle,
+        Term::AreaDeviation,
+        Term::EdgeAlignment,
+    ];
+}
+
+/// a term and its weight, parsed from `term=weight` (eg: `min-angle=0.5`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TermWeight(pub Term, pub f64);
+
+impl FromStr for TermWeight {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (term, weight) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `term=weight`, got {s:?}"))?;
+        let term = Term::from_str(term.trim(), true).map_err(|e| anyhow!(e))?;
+        let weight = weight.trim().parse().with_context(|| format!("invalid weight {weight:?}"))?;
+        Ok(TermWeight(term, weight))
+    }
+}
+
+/// weighted sum of [`Term`]s. scores have one term for each [`Term`], in the order of [`Term::ALL`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Composite {
+    /// weight of each term, anything not in here has a weight of zero
+    pub weights: BTreeMap<Term, f64>,
+    /// fraction of pixels used by [`Term::PercentileDeviation`]
+    pub percentile: f64,
+}
+
+impl Default for Composite {
+    fn default() -> Self {
+        Self {
+            weights: BTreeMap::from([
+                (Term::ColorVariance, 1.0),
+                (Term::MinAngle, 0.05),
+                (Term::AreaDeviation, 0.02),
+            ]),
+            percentile: 0.05,
+        }
+    }
+}
+
+impl Composite {
+    /// the default terms, plus edge alignment
+    pub fn edge_aware() -> Self {
+        Self::default().weight(Term::EdgeAlignment, 0.1)
+    }
+
+    /// a composite scorer with no terms
+    pub fn empty() -> Self {
+        Self {
+            weights: BTreeMap::new(),
+            ..Self::default()
+        }
+    }
+
+    /// loads the weights from a JSON file (see the [module docs](self))
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        serde_json::from_reader(BufReader::new(file)).with_context(|| format!("{path:?} is not a valid scoring config"))
+    }
+
+    pub fn weight(mut self, term: Term, weight: f64) -> Self {
+        self.weights.insert(term, weight);
+        self
+    }
+
+    pub fn percentile(mut self, percentile: f64) -> Self {
+        self.percentile = percentile;
+        self
+    }
+}
+
+impl Composite {
+    fn uses(&self, term: Term) -> bool {
+        self.weights.get(&term).is_some_and(|w| *w != 0.0)
+    }
+}
+
+impl Scorer for Composite {
+    fn needs_gradient(&self) -> bool {
+        self.uses(Term::EdgeAlignment)
+    }
+
+    fn score(&self, info: ScoreInfo) -> Score {
+        let ScoreInfo { triangle, stats: image_stats, tri_size } = info;
+        let stats = image_stats.triangle(triangle);
+        let ideal_area = 3f64.sqrt() / 4.0 * tri_size * tri_size;
+
+        let penalty = |term| match term {
+            Term::ColorVariance => stats.variance().iter().sum::<f64>().sqrt() / (255.0 * 3f64.sqrt()),
+            Term::PercentileDeviation => stats.deviation_above(self.percentile) / 255.0,
+            // degenerate triangles have no squareness at all
+            Term::Squareness => 1.0 - squareness(triangle).max(0.0) / 100.0,
+            Term::MinAngle => 1.0 - triangle.min_angle() / 60.0,
+            Term::AreaDeviation => (triangle.area() - ideal_area).abs() / ideal_area,
+            Term::EdgeAlignment => {
+                let gradient = image_stats.gradient().expect("edge alignment needs the image gradient");
+                (1.0 + gradient.triangle_alignment(triangle)) / 2.0
+            }
+        };
+        let mut terms = [0.0; Term::ALL.len()];
+        for (i, term) in Term::ALL.into_iter().enumerate() {
+            if self.uses(term) {
+                terms[i] = penalty(term);
+            }
+        }
+        Score::new(&terms)
+    }
+
+    fn value(&self, score: &Score) -> f64 {
+        -Term::ALL
+            .iter()
+            .zip(score.terms())
+            .map(|(term, penalty)| self.weights.get(term).copied().unwrap_or(0.0) * penalty)
+            .sum::<f64>()
+    }
+
+    /// 0..100, 0=worst, 100=best
+    fn display_value(&self, score: &Score) -> f64 {
+        let total_weight = self.weights.values().map(|w| w.abs()).sum::<f64>();
+        if total_weight == 0.0 {
+            return 100.0;
+        }
+        100.0 * (1.0 + self.value(score) / total_weight).clamp(0.0, 1.0)
+    }
+}
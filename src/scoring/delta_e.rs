@@ -0,0 +1,102 @@
+//! perceptual color difference (ΔE) from the average color, weighted against very thin triangles
+//! the same way as [`avg_with_shape_weight`](super::avg_with_shape_weight).
+//!
+//! the image stats are in [`ColorSpace::Oklab`] or [`ColorSpace::Cielab`], where the straight line
+//! distance between two colors is their ΔE
+
+use super::{avg_with_shape_weight::AvgWithShapeWeight, color_space::ColorSpace, Score, ScoreInfo, Scorer};
+
+/// scores have the same terms as [`AvgWithShapeWeight`], with the color distance being the mean ΔE
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaE {
+    space: ColorSpace,
+}
+
+/// like [`DeltaE`], but uses the root mean square ΔE, which can be found from the variance of the colors instead
+/// of looking at every pixel, so is much faster.
+/// it is never smaller than the mean ΔE, and equal to it when every pixel is the same distance away
+#[derive(Debug, Clone, Copy)]
+pub struct FastDeltaE {
+    space: ColorSpace,
+}
+
+/// straight line distance between two colors, which is their ΔE in either space
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+fn check_space(space: ColorSpace) {
+    assert!(
+        matches!(space, ColorSpace::Oklab | ColorSpace::Cielab),
+        "ΔE needs a perceptual color space, not {space:?}"
+    );
+}
+
+impl DeltaE {
+    /// `space` must be either [`ColorSpace::Oklab`] or [`ColorSpace::Cielab`]
+    pub fn new(space: ColorSpace) -> Self {
+        check_space(space);
+        Self { space }
+    }
+}
+
+impl FastDeltaE {
+    /// `space` must be either [`ColorSpace::Oklab`] or [`ColorSpace::Cielab`]
+    pub fn new(space: ColorSpace) -> Self {
+        check_space(space);
+        Self { space }
+    }
+}
+
+impl Scorer for DeltaE {
+    fn color_space(&self) -> ColorSpace {
+        self.space
+    }
+
+    fn score(&self, info: ScoreInfo) -> Score {
+        let ScoreInfo { triangle, stats: image, tri_size: _ } = info;
+        debug_assert_eq!(image.color_space(), self.space);
+        let stats = image.triangle(triangle);
+
+        if stats.is_empty() {
+            return AvgWithShapeWeight::none();
+        }
+
+        let mean = stats.mean();
+        let mut total = 0.0;
+        image.for_each_pixel(triangle, |color, weight| total += distance(color, mean) * weight);
+        // both spaces have L from 0-100, so this already (nearly always) fits in 0-100
+        let delta_e = (total / stats.count).min(100.0);
+        AvgWithShapeWeight::with_distance(triangle, delta_e)
+    }
+
+    /// 0..100, 0=worst, 100=best
+    fn value(&self, score: &Score) -> f64 {
+        AvgWithShapeWeight.value(score)
+    }
+}
+
+impl Scorer for FastDeltaE {
+    fn color_space(&self) -> ColorSpace {
+        self.space
+    }
+
+    fn score(&self, info: ScoreInfo) -> Score {
+        let ScoreInfo { triangle, stats, tri_size: _ } = info;
+        debug_assert_eq!(stats.color_space(), self.space);
+        let stats = stats.triangle(triangle);
+
+        if stats.is_empty() {
+            return AvgWithShapeWeight::none();
+        }
+
+        // the mean squared ΔE from the average color is just the sum of the variances of each channel
+        let delta_e = stats.variance().iter().sum::<f64>().sqrt().min(100.0);
+        AvgWithShapeWeight::with_distance(triangle, delta_e)
+    }
+
+    /// 0..100, 0=worst, 100=best
+    fn value(&self, score: &Score) -> f64 {
+        AvgWithShapeWeight.value(score)
+    }
+}
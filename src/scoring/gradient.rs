@@ -0,0 +1,93 @@
+//! image gradients, for scoring how well triangle edges follow edges in the image
+
+use image::{Rgb, RgbImage};
+
+use crate::{triangle::Triangle, vec2::F64x2};
+
+/// largest possible response of the sobel kernel to 0-255 values
+const MAX_SOBEL: f64 = 4.0 * 255.0;
+
+/// the sobel gradient of the luma of an image, scaled so each component is (about) -1..1
+#[derive(Debug, Clone)]
+pub struct GradientMap {
+    width: u32,
+    height: u32,
+    gradient: Vec<F64x2>,
+}
+
+impl GradientMap {
+    pub fn new(image: &RgbImage) -> Self {
+        let (width, height) = image.dimensions();
+        let luma = |x: i64, y: i64| {
+            // repeat the edge pixels, so the border does not look like an edge
+            let x = x.clamp(0, width as i64 - 1) as u32;
+            let y = y.clamp(0, height as i64 - 1) as u32;
+            let Rgb([r, g, b]) = *image.get_pixel(x, y);
+            0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64
+        };
+        let mut gradient = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let gx = (luma(x + 1, y - 1) + 2.0 * luma(x + 1, y) + luma(x + 1, y + 1))
+                    - (luma(x - 1, y - 1) + 2.0 * luma(x - 1, y) + luma(x - 1, y + 1));
+                let gy = (luma(x - 1, y + 1) + 2.0 * luma(x, y + 1) + luma(x + 1, y + 1))
+                    - (luma(x - 1, y - 1) + 2.0 * luma(x, y - 1) + luma(x + 1, y - 1));
+                gradient.push(F64x2::new(gx, gy) / MAX_SOBEL);
+            }
+        }
+        Self { width, height, gradient }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// gradient of the pixel containing `p`, or zero outside of the image
+    pub fn at(&self, p: F64x2) -> F64x2 {
+        if p.x < 0.0 || p.y < 0.0 || p.x >= self.width as f64 || p.y >= self.height as f64 {
+            return F64x2::zero();
+        }
+        self.gradient[p.y as usize * self.width as usize + p.x as usize]
+    }
+
+    /// how well the line from `a` to `b` follows edges in the image, from -1 (lies right along strong edges)
+    /// to 1 (cuts straight across them). flat areas are 0.
+    ///
+    /// an edge in the image has its gradient pointing across it, so this is the average of how much of the
+    /// gradient runs along the line, minus how much runs across it, sampled about once a pixel
+    pub fn edge_alignment(&self, a: F64x2, b: F64x2) -> f64 {
+        let dir = b - a;
+        let len = dir.length();
+        if len == 0.0 {
+            return 0.0;
+        }
+        let along = dir / len;
+        let across = F64x2::new(-along.y, along.x);
+        let samples = len.ceil() as usize;
+        let total = (0..samples)
+            .map(|i| {
+                let g = self.at(a + dir * ((i as f64 + 0.5) / samples as f64));
+                g.dot(along).abs() - g.dot(across).abs()
+            })
+            .sum::<f64>();
+        (total / samples as f64).clamp(-1.0, 1.0)
+    }
+
+    /// [`GradientMap::edge_alignment`] of all three sides, weighted by their length
+    pub fn triangle_alignment(&self, tri: Triangle) -> f64 {
+        let sides = [(tri.0, tri.1), (tri.1, tri.2), (tri.2, tri.0)];
+        let total_len = sides.iter().map(|(a, b)| (*b - *a).length()).sum::<f64>();
+        if total_len == 0.0 {
+            return 0.0;
+        }
+        sides
+            .iter()
+            .map(|(a, b)| self.edge_alignment(*a, *b) * (*b - *a).length())
+            .sum::<f64>()
+            / total_len
+    }
+}
@@ -1,13 +1,76 @@
-//! the current situation here is not great, it will be fixed eventually but it works for now
+//! scoring of how well triangles fit an image. see [`Scorer`]
 
 pub mod avg_with_shape_weight;
+pub mod cache;
+pub mod color_space;
+pub mod composite;
+pub mod delta_e;
+pub mod gradient;
 pub mod percentile_with_size_weight;
+pub mod raster;
+pub mod reconstruction;
+pub mod stats;
 
-use std::{cmp::Ordering, fmt::Debug};
+use std::{fmt::Debug, sync::Arc};
 
+use clap::ValueEnum;
 use image::{Rgb, RgbImage};
 
-use crate::{triangle::Triangle, vec2::F64x2, ScoringScheme};
+use crate::{triangle::Triangle, vec2::F64x2};
+pub use color_space::ColorSpace;
+pub use raster::SamplingMode;
+use stats::ImageStats;
+
+#[derive(Debug, Clone, Copy, ValueEnum, Default)]
+pub enum ScoringScheme {
+    /// percentile based system that is weighted against small triangles
+    #[default]
+    PercentileWithSizeWeight,
+    /// like `percentile-with-size-weight`, but estimates the percentile instead of sorting every pixel (much faster)
+    FastPercentileWithSizeWeight,
+    /// average based, weighted against very thin triangles
+    AvgWithShapeWeight,
+    /// like `avg-with-shape-weight`, but uses the root mean square color distance (much faster)
+    FastAvgWithShapeWeight,
+    /// perceptual color difference (ΔE in OKLab) from the average color, weighted against very thin triangles
+    DeltaEOklab,
+    /// perceptual color difference (ΔE*76 in CIELAB) from the average color, weighted against very thin triangles
+    DeltaECielab,
+    /// like `delta-e-oklab`, but uses the root mean square ΔE (much faster)
+    FastDeltaEOklab,
+    /// like `delta-e-cielab`, but uses the root mean square ΔE (much faster)
+    FastDeltaECielab,
+    /// total squared error of the flat shaded triangles against the image (minimizes the MSE of the output)
+    ReconstructionError,
+    /// total structural dissimilarity (1 - SSIM) of the flat shaded triangles against the image
+    ReconstructionSsim,
+    /// weighted sum of configurable terms (see [`composite`])
+    Composite,
+    /// color variance, plus rewarding triangle edges that follow edges in the image
+    EdgeAware,
+}
+
+impl ScoringScheme {
+    /// the scorer that implements this scheme
+    pub fn scorer(self) -> Arc<dyn Scorer> {
+        match self {
+            ScoringScheme::PercentileWithSizeWeight => Arc::new(percentile_with_size_weight::PercentileWithSizeWeight),
+            ScoringScheme::FastPercentileWithSizeWeight => {
+                Arc::new(percentile_with_size_weight::FastPercentileWithSizeWeight)
+            }
+            ScoringScheme::AvgWithShapeWeight => Arc::new(avg_with_shape_weight::AvgWithShapeWeight),
+            ScoringScheme::FastAvgWithShapeWeight => Arc::new(avg_with_shape_weight::FastAvgWithShapeWeight),
+            ScoringScheme::DeltaEOklab => Arc::new(delta_e::DeltaE::new(ColorSpace::Oklab)),
+            ScoringScheme::DeltaECielab => Arc::new(delta_e::DeltaE::new(ColorSpace::Cielab)),
+            ScoringScheme::FastDeltaEOklab => Arc::new(delta_e::FastDeltaE::new(ColorSpace::Oklab)),
+            ScoringScheme::FastDeltaECielab => Arc::new(delta_e::FastDeltaE::new(ColorSpace::Cielab)),
+            ScoringScheme::ReconstructionError => Arc::new(reconstruction::SquaredError),
+            ScoringScheme::ReconstructionSsim => Arc::new(reconstruction::Ssim),
+            ScoringScheme::Composite => Arc::new(composite::Composite::default()),
+            ScoringScheme::EdgeAware => Arc::new(composite::Composite::edge_aware()),
+        }
+    }
+}
 
 #[inline(always)]
 fn min(a: f64, b: f64) -> f64 {
@@ -47,100 +110,14 @@ pub fn point_in_triangle(pt: F64x2, v1: F64x2, v2: F64x2, v3: F64x2) -> bool {
     !(has_neg && has_pos)
 }
 
+/// colors of every pixel in the triangle (see [`raster`] for which pixels those are)
 pub fn get_color_in_triangle(image: &RgbImage, triangle: Triangle) -> Vec<Rgb<u8>> {
-    let minx = min(min(triangle.0.x, triangle.1.x), triangle.2.x)
-        .floor()
-        .clamp(0.0, image.width() as f64) as u32;
-    let maxx = max(max(triangle.0.x, triangle.1.x), triangle.2.x)
-        .ceil()
-        .clamp(0.0, image.width() as f64) as u32;
-    let miny = min(min(triangle.0.y, triangle.1.y), triangle.2.y)
-        .floor()
-        .clamp(0.0, image.height() as f64) as u32;
-    let maxy = max(max(triangle.0.y, triangle.1.y), triangle.2.y)
-        .ceil()
-        .clamp(0.0, image.height() as f64) as u32;
-    let width = maxx - minx;
-    let height = maxy - miny;
-    // println!("img: {}x{}", image.width(), image.height());
-    // println!("    sub image coords: ({}, {})", minx, miny);
-    // println!("    sub image demensions: {}x{}", width, height);
-
-    // for (x, y, pxl) in image.view(minx, miny, width, height).enumerate_pixels()
-    SubImageIterator::new(image, minx, miny, width, height)
-        .map(|(x, y, pxl)| {
-            if point_in_triangle(
-                F64x2::new((x + minx) as f64, (y + miny) as f64),
-                triangle.0,
-                triangle.1,
-                triangle.2,
-            ) {
-                Some(pxl)
-            } else {
-                None
-            }
-        })
-        .filter(Option::is_some)
-        .map(Option::unwrap)
+    raster::pixels(triangle, image.width(), image.height())
+        .map(|(x, y)| *image.get_pixel(x, y))
         .collect()
 }
 
-pub struct SubImageIterator<'a> {
-    image: &'a RgbImage,
-    xy: (u32, u32),
-    wh: (u32, u32),
-    current_xy: (u32, u32),
-    done: bool,
-}
-
-impl<'a> SubImageIterator<'a> {
-    pub fn new(img: &'a RgbImage, x: u32, y: u32, width: u32, height: u32) -> Self {
-        assert!(x + width <= img.width());
-        assert!(y + height <= img.height());
-        let done = width == 0 || height == 0;
-        SubImageIterator {
-            image: img,
-            xy: (x, y),
-            wh: (width, height),
-            current_xy: (0, 0),
-            done,
-        }
-    }
-
-    fn get(&self, x: u32, y: u32) -> Rgb<u8> {
-        *self.image.get_pixel(x + self.xy.0, y + self.xy.1)
-    }
-}
-
-impl<'a> Iterator for SubImageIterator<'a> {
-    type Item = (u32, u32, Rgb<u8>);
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.done {
-            None
-        } else {
-            let v = self.get(self.current_xy.0, self.current_xy.1);
-            self.current_xy.0 += 1;
-            if self.current_xy.0 >= self.wh.0 {
-                self.current_xy.0 = 0;
-                self.current_xy.1 += 1;
-                if self.current_xy.1 >= self.wh.1 {
-                    self.done = true;
-                }
-            }
-            Some((self.current_xy.0, self.current_xy.1, v))
-        }
-    }
-
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        if let Ok(v) = usize::try_from(self.wh.0 * self.wh.1) {
-            (v, Some(v))
-        } else {
-            (0, None)
-        }
-    }
-}
-
-pub fn average(colors: &Vec<Rgb<u8>>) -> Rgb<u8> {
+pub fn average(colors: &[Rgb<u8>]) -> Rgb<u8> {
     let sum = colors.iter().fold(Rgb([0u128; 3]), |acc, x| {
         Rgb([
             acc[0] + x[0] as u128,
@@ -155,107 +132,141 @@ pub fn average(colors: &Vec<Rgb<u8>>) -> Rgb<u8> {
     ])
 }
 
-pub trait Score: Debug + Clone + PartialEq {
-    fn score_for<'a>(info: ScoreInfo<'a>) -> Self;
-    fn average(scores: &[Self]) -> Self;
-    fn cmp(&self, other: &Self) -> Ordering;
+/// scores triangles by how well they represent the part of the image they cover.
+///
+/// the built in schemes are all implementations of this (see [`ScoringScheme::scorer`]), and any other
+/// implementation can be used by passing it to [`FitConfig::scorer`](crate::FitConfig::scorer)
+pub trait Scorer: Debug + Send + Sync {
+    /// the color space the [`ImageStats`] passed to [`Scorer::score`] are in
+    fn color_space(&self) -> ColorSpace {
+        ColorSpace::Srgb
+    }
+
+    /// if this needs [`ImageStats::gradient`] to be set
+    fn needs_gradient(&self) -> bool {
+        false
+    }
+
+    /// scores a single triangle
+    fn score(&self, info: ScoreInfo) -> Score;
+
+    /// combines the scores of a group of triangles (eg: the ones around a vertex) into one.
+    /// this is the average of each term by default, but can be anything (eg: the total)
+    fn combine(&self, scores: &[Score]) -> Score {
+        Score::mean(scores)
+    }
+
+    /// how good a (single or combined) score is, larger is better.
+    /// this is what the optimizer compares, so it only has to make sense relative to other scores from the same scorer
+    fn value(&self, score: &Score) -> f64;
+
     /// 0=worst, 100=best. must be in that range
     ///
-    /// the identity `a.cmp(b) == a.score_value().cmp(b.score_value())`
-    /// may not be relied uppon, and implementations are permitted to make this mistake.
-    /// this should only be used for visualizing the score
-    fn score_value(&self) -> f64;
+    /// this does not have to agree with [`Scorer::value`], and should only be used for visualizing the score
+    fn display_value(&self, score: &Score) -> f64 {
+        self.value(score).clamp(0.0, 100.0)
+    }
 }
 
-#[derive(Debug)]
-pub struct ScoreInfo<'a> {
-    // the triangle in question
-    triangle: Triangle,
-    // the image that the triangle is in
-    image: &'a RgbImage,
-    // extra info
-    // size of triangles
-    tri_size: f64,
-}
+/// max number of terms in a [`Score`]
+pub const MAX_TERMS: usize = 8;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum ScoreWrapper {
-    PercentileWithSizeWeight(percentile_with_size_weight::Score),
-    AvgWithShapeWeight(avg_with_shape_weight::Score),
+/// the result of scoring a triangle: up to [`MAX_TERMS`] numbers, the meaning of which is up to the [`Scorer`] that made it
+///
+/// each score also has a weight (1 by default), which is how much it counts for when combined with others.
+/// [`score`] sets this to the importance of the triangle if the image has a weight map (see [`ImageStats::importance`])
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    terms: [f64; MAX_TERMS],
+    weight: f64,
 }
 
-impl ScoreWrapper {
-    pub fn score_for(
-        info: ScoreInfo,
-        scheme: ScoringScheme,
-    ) -> Self {
-        match scheme {
-            ScoringScheme::AvgWithShapeWeight => Self::AvgWithShapeWeight(Score::score_for(info)),
-            ScoringScheme::PercentileWithSizeWeight => Self::PercentileWithSizeWeight(Score::score_for(info))
+impl Default for Score {
+    fn default() -> Self {
+        Self {
+            terms: [0.0; MAX_TERMS],
+            weight: 1.0,
         }
     }
+}
 
-    pub fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (Self::PercentileWithSizeWeight(a), Self::PercentileWithSizeWeight(b)) => a.cmp(b),
-            (Self::AvgWithShapeWeight(a), Self::AvgWithShapeWeight(b)) => a.cmp(b),
-            _ => unreachable!()
-        }
+impl Score {
+    /// panics if there are more than [`MAX_TERMS`] terms
+    pub fn new(terms: &[f64]) -> Self {
+        let mut score = Self::default();
+        score.terms[..terms.len()].copy_from_slice(terms);
+        score
     }
 
-    pub fn score_value(&self) -> f64 {
-        match self {
-            Self::PercentileWithSizeWeight(a) => a.score_value(),
-            Self::AvgWithShapeWeight(a) => a.score_value(),
-        }
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// all of the terms, any that were not given are zero
+    pub fn terms(&self) -> &[f64; MAX_TERMS] {
+        &self.terms
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
     }
 
-    pub unsafe fn average(scores: &[ScoreWrapper]) -> ScoreWrapper {
-        union ScoreTypes {
-            perentile_with_size_weight: percentile_with_size_weight::Score,
-            avg_with_shape_weight: avg_with_shape_weight::Score,
+    /// (weighted) average of each term. if all of the weights are zero, every score counts the same
+    pub fn mean(scores: &[Score]) -> Score {
+        if scores.is_empty() {
+            return Score::default();
         }
-        let union_scores = scores.iter().cloned().map(|score| match score {
-            ScoreWrapper::PercentileWithSizeWeight(perentile_with_size_weight) => ScoreTypes { perentile_with_size_weight },
-            ScoreWrapper::AvgWithShapeWeight(avg_with_shape_weight) => ScoreTypes { avg_with_shape_weight },
-        });
-
-        match &scores[0] {
-            ScoreWrapper::PercentileWithSizeWeight(..) => {
-                ScoreWrapper::PercentileWithSizeWeight(Score::average(&union_scores.map(|st| st.perentile_with_size_weight).collect::<Vec<_>>()))
-            }
-            ScoreWrapper::AvgWithShapeWeight(..) => {
-                ScoreWrapper::AvgWithShapeWeight(Score::average(&union_scores.map(|st| st.avg_with_shape_weight).collect::<Vec<_>>()))
+        let total_weight = scores.iter().map(|s| s.weight).sum::<f64>();
+        if total_weight <= 0.0 {
+            let unweighted = scores.iter().map(|s| s.with_weight(1.0)).collect::<Vec<_>>();
+            return Score::mean(&unweighted).with_weight(0.0);
+        }
+        let mut mean = Score::sum(scores);
+        mean.terms.iter_mut().for_each(|t| *t /= total_weight);
+        mean
+    }
+
+    /// (weighted) total of each term. the weight of the result is the average weight
+    pub fn sum(scores: &[Score]) -> Score {
+        let mut sum = Score::default();
+        for score in scores {
+            for (t, s) in sum.terms.iter_mut().zip(score.terms) {
+                *t += s * score.weight;
             }
         }
+        if !scores.is_empty() {
+            sum.weight = scores.iter().map(|s| s.weight).sum::<f64>() / scores.len() as f64;
+        }
+        sum
     }
 }
 
-pub fn score(
-    triangle: Triangle,
-    image: &RgbImage,
-    tri_size: f64,
-    scheme: ScoringScheme,
-) -> ScoreWrapper {
-    ScoreWrapper::score_for(ScoreInfo { triangle, image, tri_size }, scheme)
+/// everything a [`Scorer`] gets to look at
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreInfo<'a> {
+    /// the triangle in question
+    pub triangle: Triangle,
+    /// color stats of the image that the triangle is in (in [`Scorer::color_space`])
+    pub stats: &'a ImageStats,
+    // extra info
+    /// size of triangles
+    pub tri_size: f64,
 }
 
-pub fn score_for_group(
-    image: &RgbImage,
-    group: &Vec<Triangle>,
-    tri_size: f64,
-    scheme: ScoringScheme,
-) -> ScoreWrapper {
-    assert!(!group.is_empty());// will break average fn otherwise
-    let scores = group.iter().map(|t| {
-        ScoreWrapper::score_for(
-            ScoreInfo {
-                triangle: *t,
-                image,
-                tri_size,
-            },
-            scheme
-        )
-    });
-    unsafe { ScoreWrapper::average(&scores.collect::<Vec<_>>()) }
+/// scores a triangle, weighted by its importance if the image has a weight map
+pub fn score(triangle: Triangle, stats: &ImageStats, tri_size: f64, scorer: &dyn Scorer) -> Score {
+    let score = scorer.score(ScoreInfo { triangle, stats, tri_size });
+    match stats.has_weight_map() {
+        true => score.with_weight(stats.importance(triangle)),
+        false => score,
+    }
+}
+
+pub fn score_for_group(stats: &ImageStats, group: &[Triangle], tri_size: f64, scorer: &dyn Scorer) -> Score {
+    let scores = group
+        .iter()
+        .map(|t| score(*t, stats, tri_size, scorer))
+        .collect::<Vec<_>>();
+    scorer.combine(&scores)
 }
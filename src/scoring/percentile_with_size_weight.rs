@@ -1,78 +1,104 @@
-use lazysort::SortedBy;
+use super::{
+    stats::{ImageStats, TriangleStats},
+    Score, ScoreInfo, Scorer,
+};
+use crate::triangle::Triangle;
 
-use super::{ScoreInfo, get_color_in_triangle, average};
+/// percentile based system that is weighted against small triangles
+///
+/// scores have one term, which is smaller for better triangles
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PercentileWithSizeWeight;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Score(f64 /* smaller is better */);
+/// like [`PercentileWithSizeWeight`], but estimates the percentile from the variance of the colors (assuming they are
+/// normally distributed) instead of sorting every pixel, which is much faster
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FastPercentileWithSizeWeight;
 
-impl super::Score for Score {
-    fn score_for<'a>(info: super::ScoreInfo<'a>) -> Self {
-        let ScoreInfo { triangle, image, tri_size } = info;
-        let colors = get_color_in_triangle(image, triangle);
+/// works out the score, with `base(image, triangle, stats, nth)` being how far off the average color the pixel `nth`
+/// furthest from it is (in its worst channel)
+fn score_with(info: ScoreInfo, base: impl FnOnce(&ImageStats, Triangle, &TriangleStats, usize) -> f64) -> Score {
+    let ScoreInfo { triangle, stats: image, tri_size } = info;
+    let (width, height) = (image.width(), image.height());
+    let stats = image.triangle(triangle);
+    let count = stats.count;
 
-        let w = image.width() + (tri_size - image.width() as f64 % tri_size.ceil()) as u32;
-        let h = image.height() + (tri_size - image.height() as f64 % tri_size.ceil()) as u32;
-        let appt = (image.width() * image.height())
-            / ((w as f64 / tri_size) * (h as f64 / tri_size)) as u32;
+    let w = width + (tri_size - width as f64 % tri_size.ceil()) as u32;
+    let h = height + (tri_size - height as f64 % tri_size.ceil()) as u32;
+    let appt = (width * height)
+        / ((w as f64 / tri_size) * (h as f64 / tri_size)) as u32;
 
-        fn max(a: f64, b: f64) -> f64 {
-            if a > b {
-                a
-            } else {
-                b
-            }
-        }
+    let nth = std::cmp::min(
+        appt as usize / 20, // 5%
+        (count as usize).saturating_sub(1),
+    );
+    let base = base(image, triangle, &stats, nth);
+    // let size_score = (deviations.len() - std::cmp::min(appt as usize / 20 /* 5% */, deviations.len())) as f64 * 1.0 /* weight value */;
+    // let size_score = if (appt as f64 * 0.03) as usize > deviations.len() {
+    //     (appt as f64 * 0.03) as usize - deviations.len()
+    // } else { 0 } as f64;
+    // let size_score = 1.0 / (((cmp::min(deviations.len() as u32 + 1, (appt as f64 / 1.0) as u32) as f64 * 10.0)
+    // / (appt as f64 / 1.0)) * 2.0);
+    let size_score = ((appt as f64 / (count + 1.0)) / appt as f64) * 255.0;
+    // println!("{}", size_score);
+    if base + size_score > 255.0 * 3.0 {
+        println!("{base} {size_score} {} {}", count, appt);
+    }
+    let ret = base + size_score;
+    //     let r = deviations.iter().sum::<f64>() / deviations.len() as f64;
+    //     if r.is_nan() {
+    //         0.0
+    //     } else {
+    //         r
+    //     }
 
-        let avg = average(&colors);
-        let base = colors
-            .iter()
-            .map(|c| {
-                max(
-                    max(
-                        (avg.0[0] as f64 - c.0[0] as f64).abs(),
-                        (avg.0[1] as f64 - c.0[1] as f64).abs(),
-                    ),
-                    (avg.0[2] as f64 - c.0[2] as f64).abs(),
-                )
-            })
-            .sorted_by(|a, b| b.total_cmp(a))
-            .nth(std::cmp::min(
-                appt as usize / 20, // 5%
-                colors.len().saturating_sub(1),
-            ))
-            .unwrap_or(0.0);
-        // let size_score = (deviations.len() - std::cmp::min(appt as usize / 20 /* 5% */, deviations.len())) as f64 * 1.0 /* weight value */;
-        // let size_score = if (appt as f64 * 0.03) as usize > deviations.len() {
-        //     (appt as f64 * 0.03) as usize - deviations.len()
-        // } else { 0 } as f64;
-        // let size_score = 1.0 / (((cmp::min(deviations.len() as u32 + 1, (appt as f64 / 1.0) as u32) as f64 * 10.0)
-        // / (appt as f64 / 1.0)) * 2.0);
-        let size_score = ((appt as f64 / (colors.len() as f64 + 1.0)) / appt as f64) * 255.0;
-        // println!("{}", size_score);
-        if base + size_score > 255.0 * 3.0 {
-            println!("{base} {size_score} {} {}", colors.len(), appt);
-        }
-        let ret = base + size_score;
-        //     let r = deviations.iter().sum::<f64>() / deviations.len() as f64;
-        //     if r.is_nan() {
-        //         0.0
-        //     } else {
-        //         r
-        //     }
+    Score::new(&[ret])
+}
 
-        Score(ret)
+impl Scorer for PercentileWithSizeWeight {
+    fn score(&self, info: ScoreInfo) -> Score {
+        score_with(info, |image, triangle, stats, nth| {
+            // the average is rounded down, like the colors themselves
+            let avg = stats.mean().map(f64::floor);
+            let mut deviations = vec![];
+            image.for_each_pixel(triangle, |color, weight| {
+                let deviation = (0..3).map(|c| (avg[c] - color[c]).abs()).fold(0.0, f64::max);
+                deviations.push((deviation, weight));
+            });
+            deviations.sort_by(|a, b| b.0.total_cmp(&a.0));
+            // partly covered pixels only count for part of a pixel
+            let mut seen = 0.0;
+            deviations
+                .iter()
+                .find(|(_, weight)| {
+                    seen += weight;
+                    seen > nth as f64
+                })
+                .or(deviations.last())
+                .map_or(0.0, |&(deviation, _)| deviation)
+        })
     }
 
-    fn average(scores: &[Self]) -> Self {
-        Score(if !scores.is_empty() { scores.iter().copied().map(|Score(score)| score).sum::<f64>() / scores.len() as f64 } else { 0.0 })
+    fn value(&self, score: &Score) -> f64 {
+        -score.terms()[0]
     }
 
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.0.partial_cmp(&self.0).unwrap()
+    /// 0..100, 0=worst, 100=best
+    fn display_value(&self, score: &Score) -> f64 {
+        100.0 - (score.terms()[0] / 2.5).clamp(0.0, 100.0) // may be wrong
     }
+}
 
-    /// 0..100, 0=worst, 100=best
-    fn score_value(&self) -> f64 {
-        100.0 - (self.0 / 2.5).clamp(0.0, 100.0) // may be wrong
+impl Scorer for FastPercentileWithSizeWeight {
+    fn score(&self, info: ScoreInfo) -> Score {
+        score_with(info, |_, _, stats, nth| stats.deviation_above((nth as f64 + 0.5) / stats.count))
+    }
+
+    fn value(&self, score: &Score) -> f64 {
+        PercentileWithSizeWeight.value(score)
+    }
+
+    fn display_value(&self, score: &Score) -> f64 {
+        PercentileWithSizeWeight.display_value(score)
     }
 }
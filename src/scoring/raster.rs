@@ -0,0 +1,401 @@
+//! scanline rasterization of triangles.
+//!
+//! a pixel belongs to a triangle if its center is inside it. centers that land exactly on an edge
+//! follow the top-left rule: they belong to the triangle if the edge is a left edge or a flat top edge.
+//! this means that in a mesh every pixel is covered by exactly one triangle, even along shared edges.
+//!
+//! alternatively, [`for_each_covered`] gives every pixel a triangle touches along with how much of it is covered
+
+use clap::ValueEnum;
+
+use crate::{triangle::Triangle, vec2::F64x2};
+
+/// a run of pixels `x0..x1` on row `y`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub y: u32,
+    pub x0: u32,
+    pub x1: u32,
+}
+
+/// iterator over the rows of pixels covered by a triangle, clipped to the image
+#[derive(Debug, Clone)]
+pub struct Spans {
+    /// corners sorted top to bottom
+    verts: [F64x2; 3],
+    width: u32,
+    y: u32,
+    y_end: u32,
+}
+
+impl Spans {
+    pub fn new(tri: Triangle, width: u32, height: u32) -> Self {
+        let mut verts = [tri.0, tri.1, tri.2];
+        // sorting on x as well makes sure the two triangles sharing an edge always see its ends in the same
+        // order, so they work out exactly the same crossing points
+        verts.sort_by(|a, b| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+        let first_row = |y: f64| (y - 0.5).ceil().clamp(0.0, height as f64) as u32;
+        Self {
+            verts,
+            width,
+            y: first_row(verts[0].y),
+            y_end: first_row(verts[2].y),
+        }
+    }
+}
+
+/// x where the edge from `a` to `b` (with `a` above `b`) crosses the horizontal line at `y`
+#[inline(always)]
+fn edge_x(a: F64x2, b: F64x2, y: f64) -> f64 {
+    a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y)
+}
+
+impl Iterator for Spans {
+    type Item = Span;
+
+    fn next(&mut self) -> Option<Span> {
+        let [top, mid, bottom] = self.verts;
+        while self.y < self.y_end {
+            let y = self.y;
+            self.y += 1;
+            // pixel centers are at +0.5
+            let center = y as f64 + 0.5;
+            let long = edge_x(top, bottom, center);
+            let short = if center < mid.y {
+                edge_x(top, mid, center)
+            } else {
+                edge_x(mid, bottom, center)
+            };
+            let (left, right) = if long <= short { (long, short) } else { (short, long) };
+            // left edges are inclusive and right edges are exclusive
+            let first_col = |x: f64| (x - 0.5).ceil().clamp(0.0, self.width as f64) as u32;
+            let (x0, x1) = (first_col(left), first_col(right));
+            if x0 < x1 {
+                return Some(Span { y, x0, x1 });
+            }
+        }
+        None
+    }
+}
+
+/// iterator over every pixel covered by a triangle (clipped to the image), row by row
+pub fn pixels(tri: Triangle, width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    Spans::new(tri, width, height).flat_map(|Span { y, x0, x1 }| (x0..x1).map(move |x| (x, y)))
+}
+
+/// how pixels are sampled when working out which ones belong to a triangle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SamplingMode {
+    /// each pixel belongs entirely to the triangle its center is in
+    #[default]
+    Center,
+    /// each pixel counts towards every triangle that overlaps it, in proportion to how much of it is covered
+    Coverage,
+}
+
+/// part of a triangle, see [`for_each_covered`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Covered {
+    /// a run of pixels that are completely inside the triangle
+    Full(Span),
+    /// a pixel that is only partly covered
+    Partial { x: u32, y: u32, coverage: f64 },
+}
+
+/// calls `f` with every pixel (clipped to the image) that the triangle overlaps, along with how much of it is covered
+pub fn for_each_covered(tri: Triangle, width: u32, height: u32, mut f: impl FnMut(Covered)) {
+    let verts = [tri.0, tri.1, tri.2];
+    let miny = verts.iter().map(|v| v.y).fold(f64::INFINITY, f64::min);
+    let maxy = verts.iter().map(|v| v.y).fold(f64::NEG_INFINITY, f64::max);
+    let y_start = miny.floor().clamp(0.0, height as f64) as u32;
+    let y_end = maxy.ceil().clamp(0.0, height as f64) as u32;
+    let triangle = Poly::from_slice(&verts);
+    for y in y_start..y_end {
+        let (top, bottom) = (y as f64, y as f64 + 1.0);
+        let row = triangle.clip(Axis::Y, top, true).clip(Axis::Y, bottom, false);
+        if row.len < 3 {
+            continue;
+        }
+        let (xmin, xmax) = row.range(Axis::X);
+        let x_start = xmin.floor().clamp(0.0, width as f64) as u32;
+        let x_end = xmax.ceil().clamp(0.0, width as f64) as u32;
+
+        // the row is convex, so if it covers the whole height of the row, everything between where
+        // its left edges are furthest right and its right edges are furthest left is fully covered
+        let (mut full_start, mut full_end) = (x_start, x_start);
+        if miny <= top && maxy >= bottom {
+            let (top_left, top_right) = row.range_at(Axis::Y, top);
+            let (bottom_left, bottom_right) = row.range_at(Axis::Y, bottom);
+            let left = top_left.max(bottom_left).ceil().clamp(0.0, width as f64) as u32;
+            let right = top_right.min(bottom_right).floor().clamp(0.0, width as f64) as u32;
+            if left < right {
+                (full_start, full_end) = (left, right);
+            }
+        }
+
+        let partial = |x: u32, f: &mut dyn FnMut(Covered)| {
+            let coverage = row
+                .clip(Axis::X, x as f64, true)
+                .clip(Axis::X, x as f64 + 1.0, false)
+                .area();
+            if coverage > 0.0 {
+                f(Covered::Partial { x, y, coverage });
+            }
+        };
+        if full_start < full_end {
+            (x_start..full_start).for_each(|x| partial(x, &mut f));
+            f(Covered::Full(Span { y, x0: full_start, x1: full_end }));
+            (full_end..x_end).for_each(|x| partial(x, &mut f));
+        } else {
+            (x_start..x_end).for_each(|x| partial(x, &mut f));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    X,
+    Y,
+}
+
+impl Axis {
+    fn of(self, p: F64x2) -> f64 {
+        match self {
+            Axis::X => p.x,
+            Axis::Y => p.y,
+        }
+    }
+}
+
+/// small convex polygon, for clipping triangles to pixels.
+/// a triangle clipped to a box can never have more than 7 corners
+#[derive(Debug, Clone, Copy)]
+struct Poly {
+    points: [F64x2; 8],
+    len: usize,
+}
+
+impl Poly {
+    fn from_slice(points: &[F64x2]) -> Self {
+        let mut poly = Self { points: [F64x2::zero(); 8], len: 0 };
+        points.iter().for_each(|p| poly.push(*p));
+        poly
+    }
+
+    fn push(&mut self, p: F64x2) {
+        self.points[self.len] = p;
+        self.len += 1;
+    }
+
+    fn points(&self) -> &[F64x2] {
+        &self.points[..self.len]
+    }
+
+    /// keeps the part of the polygon on one side of the line `axis = value` (Sutherland-Hodgman)
+    fn clip(&self, axis: Axis, value: f64, keep_greater: bool) -> Self {
+        let inside = |p: F64x2| if keep_greater { axis.of(p) >= value } else { axis.of(p) <= value };
+        let mut out = Self { points: [F64x2::zero(); 8], len: 0 };
+        for (i, &cur) in self.points().iter().enumerate() {
+            let prev = self.points[(i + self.len - 1) % self.len];
+            if inside(cur) != inside(prev) {
+                let t = (value - axis.of(prev)) / (axis.of(cur) - axis.of(prev));
+                let mut crossing = prev + (cur - prev) * t;
+                // land exactly on the line, so the edge can be found again with `range_at`
+                match axis {
+                    Axis::X => crossing.x = value,
+                    Axis::Y => crossing.y = value,
+                }
+                out.push(crossing);
+            }
+            if inside(cur) {
+                out.push(cur);
+            }
+        }
+        out
+    }
+
+    /// min and max of the polygon along `axis`
+    fn range(&self, axis: Axis) -> (f64, f64) {
+        self.points().iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+            (lo.min(axis.of(*p)), hi.max(axis.of(*p)))
+        })
+    }
+
+    /// range (along the other axis) of the corners lying on the line `axis = value`
+    fn range_at(&self, axis: Axis, value: f64) -> (f64, f64) {
+        let other = match axis {
+            Axis::X => Axis::Y,
+            Axis::Y => Axis::X,
+        };
+        self.points()
+            .iter()
+            .filter(|p| axis.of(**p) == value)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+                (lo.min(other.of(*p)), hi.max(other.of(*p)))
+            })
+    }
+
+    fn area(&self) -> f64 {
+        if self.len < 3 {
+            return 0.0;
+        }
+        let twice = self
+            .points()
+            .iter()
+            .zip(self.points().iter().cycle().skip(1))
+            .map(|(a, b)| a.cross_2v(*b))
+            .sum::<f64>();
+        twice.abs() / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mesh::{Mesh, TriMesh},
+        scoring::point_in_triangle,
+        triangle::Triangles,
+    };
+
+    /// if `p` is on the segment from `a` to `b`
+    fn on_segment(p: F64x2, a: F64x2, b: F64x2) -> bool {
+        (b - a).cross_2v(p - a) == 0.0
+            && p.x >= a.x.min(b.x)
+            && p.x <= a.x.max(b.x)
+            && p.y >= a.y.min(b.y)
+            && p.y <= a.y.max(b.y)
+    }
+
+    /// rasterizes every triangle of `mesh`, and checks that each pixel with its center inside the mesh is
+    /// yielded exactly once (the ones with their center on the outside edge of the mesh can be either way),
+    /// and no others are
+    fn assert_covered_once(mesh: &TriMesh, width: u32, height: u32) {
+        let mut counts = vec![0u32; (width * height) as usize];
+        for tri in mesh.triangles() {
+            for (x, y) in pixels(tri, width, height) {
+                counts[(y * width + x) as usize] += 1;
+            }
+        }
+        let outside_edges = (0..mesh.face_count() * 3)
+            .filter(|&h| mesh.twin(h).is_none())
+            .map(|h| (mesh.positions()[mesh.origin(h)], mesh.positions()[mesh.dest(h)]))
+            .collect::<Vec<_>>();
+        for y in 0..height {
+            for x in 0..width {
+                let center = F64x2::new(x as f64 + 0.5, y as f64 + 0.5);
+                let count = counts[(y * width + x) as usize];
+                let inside = mesh.triangles().iter().any(|t| point_in_triangle(center, t.0, t.1, t.2));
+                if outside_edges.iter().any(|&(a, b)| on_segment(center, a, b)) {
+                    assert!(count <= 1, "pixel ({x}, {y}) on the edge of the mesh was yielded {count} times");
+                } else if inside {
+                    assert_eq!(count, 1, "pixel ({x}, {y}) was yielded {count} times");
+                } else {
+                    assert_eq!(count, 0, "pixel ({x}, {y}) outside the mesh was yielded {count} times");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_covers_each_pixel_once() {
+        let (width, height) = (40, 30);
+        let mut grid = Triangles::for_image(width, height, 6.0);
+        // every vertex on a pixel center, so lots of edges go right through pixel centers
+        for v in grid.verts() {
+            grid.set_vert(v, grid.vert(v) + F64x2::new(0.5, 0.5));
+        }
+        // and the insides moved off of them by fractions of a pixel
+        let mut jittered = grid.clone();
+        for (x, y) in jittered.verts() {
+            if !jittered.vert_is_edge(x, y) {
+                let offset = F64x2::new(((x * 7 + y * 3) % 11) as f64 * 0.13 - 0.65, ((x * 5 + y) % 7) as f64 * 0.21 - 0.63);
+                jittered.set_vert((x, y), jittered.vert((x, y)) + offset);
+            }
+        }
+        for grid in [grid, jittered] {
+            assert_covered_once(&TriMesh::from(&grid), width, height);
+        }
+    }
+
+    #[test]
+    fn trimesh_covers_each_pixel_once() {
+        // a rectangle with fractional corners, split into four around a vertex on a pixel center, with each
+        // quarter split in two along a diagonal
+        let xs = [0.3, 5.5, 20.7];
+        let ys = [0.6, 7.5, 15.2];
+        let verts = ys
+            .iter()
+            .flat_map(|&y| xs.iter().map(move |&x| F64x2::new(x, y)))
+            .collect::<Vec<_>>();
+        let mut faces = vec![];
+        for y in 0..2 {
+            for x in 0..2 {
+                let [a, b, c, d] = [y * 3 + x, y * 3 + x + 1, (y + 1) * 3 + x + 1, (y + 1) * 3 + x];
+                faces.push([a, b, c]);
+                faces.push([a, c, d]);
+            }
+        }
+        let mut on_border = vec![true; verts.len()];
+        on_border[4] = false;
+        let mesh = TriMesh::from_parts(verts, on_border, faces, 10.0);
+        assert!(mesh.triangles().iter().all(|t| t.signed_area() > 0.0));
+        assert_covered_once(&mesh, 24, 18);
+    }
+
+    /// total coverage of each pixel by `tris`
+    fn coverage(tris: &[Triangle], width: u32, height: u32) -> Vec<f64> {
+        let mut coverage = vec![0.0; (width * height) as usize];
+        for tri in tris {
+            for_each_covered(*tri, width, height, |covered| match covered {
+                Covered::Full(Span { y, x0, x1 }) => (x0..x1).for_each(|x| coverage[(y * width + x) as usize] += 1.0),
+                Covered::Partial { x, y, coverage: c } => coverage[(y * width + x) as usize] += c,
+            });
+        }
+        coverage
+    }
+
+    #[test]
+    fn coverage_adds_up_to_area() {
+        let tris = [
+            Triangle(F64x2::new(1.3, 2.7), F64x2::new(17.9, 4.1), F64x2::new(6.2, 15.5)),
+            // very thin
+            Triangle(F64x2::new(0.5, 0.5), F64x2::new(19.5, 1.25), F64x2::new(19.5, 1.5)),
+            // corners on pixel edges and centers
+            Triangle(F64x2::new(2.0, 3.0), F64x2::new(12.0, 3.0), F64x2::new(7.5, 13.5)),
+            // smaller than a pixel
+            Triangle(F64x2::new(4.1, 4.2), F64x2::new(4.8, 4.3), F64x2::new(4.4, 4.9)),
+        ];
+        for tri in tris {
+            let covered = coverage(&[tri], 20, 20).iter().sum::<f64>();
+            assert!((covered - tri.area()).abs() < 1e-9, "{tri:?} covers {covered}, but has an area of {}", tri.area());
+        }
+    }
+
+    #[test]
+    fn coverage_of_a_mesh_adds_up_to_one() {
+        // a mesh covering the whole image, split into four around a vertex that is not on a pixel edge
+        let (width, height) = (24, 18);
+        let xs = [0.0, 9.37, width as f64];
+        let ys = [0.0, 7.81, height as f64];
+        let verts = ys
+            .iter()
+            .flat_map(|&y| xs.iter().map(move |&x| F64x2::new(x, y)))
+            .collect::<Vec<_>>();
+        let mut faces = vec![];
+        for y in 0..2 {
+            for x in 0..2 {
+                let [a, b, c, d] = [y * 3 + x, y * 3 + x + 1, (y + 1) * 3 + x + 1, (y + 1) * 3 + x];
+                faces.push([a, b, c]);
+                faces.push([a, c, d]);
+            }
+        }
+        let mut mesh = TriMesh::from_parts(verts, vec![true; 9], faces, 10.0);
+        for offset in [F64x2::zero(), F64x2::new(3.3, -2.45)] {
+            mesh.set_vert(4, F64x2::new(9.37, 7.81) + offset);
+            for (i, c) in coverage(&mesh.triangles(), width, height).into_iter().enumerate() {
+                assert!((c - 1.0).abs() < 1e-9, "pixel ({}, {}) is covered {c} times", i as u32 % width, i as u32 / width);
+            }
+        }
+    }
+}
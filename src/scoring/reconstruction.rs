@@ -0,0 +1,87 @@
+//! how far the flat shaded triangle (filled with its average color) is from the image under it.
+//!
+//! unlike the other schemes, scores of a group are added up rather than averaged, so the score of the
+//! triangles around a vertex is the total error over the area they cover, and minimizing it minimizes
+//! the error of the whole rendered image.
+//!
+//! scores from both scorers have two terms, the total loss (smaller is better) and the number of pixels it is over
+
+use super::{stats::TriangleStats, Score, ScoreInfo, Scorer};
+
+/// stabilizing constant from the SSIM paper, `(0.03 * 255)^2`
+const SSIM_C2: f64 = 58.5225;
+
+/// the total of each term. the losses are already weighted by the weight map (if there is one) pixel by pixel,
+/// so they are not weighted again by the importance of each triangle
+fn total(scores: &[Score]) -> Score {
+    Score::sum(&scores.iter().map(|s| s.with_weight(1.0)).collect::<Vec<_>>())
+}
+
+/// sum of squared differences (over every channel) between the image and the triangles fill color
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SquaredError;
+
+impl Scorer for SquaredError {
+    fn score(&self, info: ScoreInfo) -> Score {
+        let ScoreInfo { triangle, stats, tri_size: _ } = info;
+        let stats = stats.triangle(triangle);
+        // the fill color is the mean, so the squared error is just the variance times the number of pixels
+        Score::new(&[stats.variance().iter().sum::<f64>() * stats.count, stats.count])
+    }
+
+    fn combine(&self, scores: &[Score]) -> Score {
+        total(scores)
+    }
+
+    fn value(&self, score: &Score) -> f64 {
+        -score.terms()[0]
+    }
+
+    /// 0..100, 0=worst, 100=best
+    fn display_value(&self, score: &Score) -> f64 {
+        let &[loss, area, ..] = score.terms();
+        if area <= 0.0 {
+            return 0.0;
+        }
+        let rmse = (loss / (area * 3.0)).sqrt();
+        100.0 - (rmse / 2.55).clamp(0.0, 100.0)
+    }
+}
+
+/// structural dissimilarity (`1 - SSIM`) between the image and the triangles fill color, times the number of pixels
+///
+/// compared to a flat color the luminance and structure parts of SSIM drop out,
+/// leaving `C2 / (variance + C2)` for each channel
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ssim;
+
+impl Ssim {
+    fn ssim(stats: &TriangleStats) -> f64 {
+        stats.variance().iter().map(|var| SSIM_C2 / (var + SSIM_C2)).sum::<f64>() / 3.0
+    }
+}
+
+impl Scorer for Ssim {
+    fn score(&self, info: ScoreInfo) -> Score {
+        let ScoreInfo { triangle, stats, tri_size: _ } = info;
+        let stats = stats.triangle(triangle);
+        Score::new(&[(1.0 - Self::ssim(&stats)) * stats.count, stats.count])
+    }
+
+    fn combine(&self, scores: &[Score]) -> Score {
+        total(scores)
+    }
+
+    fn value(&self, score: &Score) -> f64 {
+        -score.terms()[0]
+    }
+
+    /// 0..100, 0=worst, 100=best
+    fn display_value(&self, score: &Score) -> f64 {
+        let &[loss, area, ..] = score.terms();
+        if area <= 0.0 {
+            return 0.0;
+        }
+        100.0 * (1.0 - loss / area).clamp(0.0, 1.0)
+    }
+}
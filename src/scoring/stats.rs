@@ -0,0 +1,333 @@
+use std::sync::Arc;
+
+use image::{GrayImage, Luma, Rgb, RgbImage};
+
+use super::{
+    color_space::ColorSpace,
+    gradient::GradientMap,
+    raster::{for_each_covered, Covered, SamplingMode, Span, Spans},
+};
+use crate::triangle::Triangle;
+
+/// running sums of each channel (and each channel squared) along every row of an image.
+///
+/// the sum over any horizontal run of pixels is then just two lookups, so the pixel count, mean and
+/// variance of a triangle can be found by only working out where its edges cross each row
+/// (see [`Spans`]), instead of looking at every pixel inside it.
+#[derive(Debug, Clone)]
+pub struct ImageStats {
+    width: u32,
+    height: u32,
+    sampling: SamplingMode,
+    /// space the sums are in
+    space: ColorSpace,
+    /// only there for scorers that need it (see [`Scorer::needs_gradient`](super::Scorer::needs_gradient))
+    gradient: Option<Arc<GradientMap>>,
+    /// running sums of the importance of each pixel (0-1) along every row, laid out like `sums`.
+    /// see [`ImageStats::importance`]
+    weights: Option<Arc<Vec<f64>>>,
+    /// `width + 1` entries per row, the first of which is always zero. with a weight map every pixel is
+    /// multiplied by its weight first
+    sums: Vec<[f64; 3]>,
+    sq_sums: Vec<[f64; 3]>,
+    /// the value of every pixel, for things that can not be worked out from the sums (see [`ImageStats::for_each_pixel`]).
+    /// single precision is plenty for a single pixel, it is only the sums that need more
+    pixels: Vec<[f32; 3]>,
+}
+
+impl ImageStats {
+    pub fn new(image: &RgbImage) -> Self {
+        Self::in_space(image, ColorSpace::Srgb)
+    }
+
+    /// stats of the image with every pixel converted to `space` first
+    pub fn in_space(image: &RgbImage, space: ColorSpace) -> Self {
+        let mut stats = Self::from_fn(image.width(), image.height(), |x, y| space.encode(*image.get_pixel(x, y)));
+        stats.space = space;
+        stats
+    }
+
+    /// builds the sums from the (3 channel) value of each pixel given by `value(x, y)`, which are assumed to be sRGB
+    pub fn from_fn(width: u32, height: u32, value: impl Fn(u32, u32) -> [f64; 3]) -> Self {
+        let value = &value;
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| value(x, y).map(|c| c as f32)))
+            .collect();
+        let mut stats = Self {
+            width,
+            height,
+            sampling: SamplingMode::default(),
+            space: ColorSpace::Srgb,
+            gradient: None,
+            weights: None,
+            sums: vec![],
+            sq_sums: vec![],
+            pixels,
+        };
+        stats.sum_rows(|_, _| 1.0);
+        stats
+    }
+
+    /// (re)builds the running sums of each row, with every pixel multiplied by `weight(x, y)`
+    fn sum_rows(&mut self, weight: impl Fn(u32, u32) -> f64) {
+        let row_len = self.width as usize + 1;
+        self.sums = Vec::with_capacity(row_len * self.height as usize);
+        self.sq_sums = Vec::with_capacity(row_len * self.height as usize);
+        let mut pixels = self.pixels.iter();
+        for y in 0..self.height {
+            let mut sum = [0.0; 3];
+            let mut sq_sum = [0.0; 3];
+            self.sums.push(sum);
+            self.sq_sums.push(sq_sum);
+            for x in 0..self.width {
+                let v = pixels.next().unwrap();
+                let w = weight(x, y);
+                for c in 0..3 {
+                    let v = v[c] as f64;
+                    sum[c] += w * v;
+                    sq_sum[c] += w * v * v;
+                }
+                self.sums.push(sum);
+                self.sq_sums.push(sq_sum);
+            }
+        }
+    }
+
+    /// sets which pixels count towards a triangle (and by how much), see [`SamplingMode`]
+    pub fn sampling(mut self, sampling: SamplingMode) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn sampling_mode(&self) -> SamplingMode {
+        self.sampling
+    }
+
+    /// attaches the gradient of the image, for scorers that look at edges
+    pub fn with_gradient(mut self, gradient: Arc<GradientMap>) -> Self {
+        self.set_gradient(gradient);
+        self
+    }
+
+    pub fn set_gradient(&mut self, gradient: Arc<GradientMap>) {
+        assert_eq!((gradient.width(), gradient.height()), (self.width, self.height));
+        self.gradient = Some(gradient);
+    }
+
+    pub fn gradient(&self) -> Option<&GradientMap> {
+        self.gradient.as_deref()
+    }
+
+    /// uses a grayscale mask (the same size as the image) to weight how much each pixel matters.
+    /// white pixels count fully, and black ones not at all.
+    ///
+    /// every pixel is weighted by the mask wherever it is counted, so the count, mean and variance of a triangle
+    /// (and the pixels passed to [`ImageStats::for_each_pixel`]) are all weighted by it
+    pub fn with_weight_map(mut self, map: &GrayImage) -> Self {
+        self.set_weight_map(map);
+        self
+    }
+
+    pub fn set_weight_map(&mut self, map: &GrayImage) {
+        assert_eq!(map.dimensions(), (self.width, self.height));
+        let weight = |x, y| {
+            let Luma([v]) = *map.get_pixel(x, y);
+            v as f64 / 255.0
+        };
+        self.sum_rows(weight);
+        let mut weights = Vec::with_capacity((self.width as usize + 1) * self.height as usize);
+        for y in 0..self.height {
+            let mut sum = 0.0;
+            weights.push(sum);
+            for x in 0..self.width {
+                sum += weight(x, y);
+                weights.push(sum);
+            }
+        }
+        self.weights = Some(Arc::new(weights));
+    }
+
+    pub fn has_weight_map(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    /// the average weight of the pixels in a triangle (1 if there is no weight map, and 0 if it has no pixels)
+    pub fn importance(&self, tri: Triangle) -> f64 {
+        let Some(weights) = &self.weights else {
+            return 1.0;
+        };
+        let (mut weight, mut area) = (0.0, 0.0);
+        self.for_each_run(tri, |y, x0, x1, coverage| {
+            let row = y as usize * (self.width as usize + 1);
+            weight += (weights[row + x1 as usize] - weights[row + x0 as usize]) * coverage;
+            area += (x1 - x0) as f64 * coverage;
+        });
+        if area > 0.0 {
+            weight / area
+        } else {
+            0.0
+        }
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.space
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// stats for the pixels `x0..x1` of row `y`
+    pub fn span(&self, y: u32, x0: u32, x1: u32) -> TriangleStats {
+        debug_assert!(x0 <= x1 && x1 <= self.width && y < self.height);
+        let row = y as usize * (self.width as usize + 1);
+        let (a, b) = (row + x0 as usize, row + x1 as usize);
+        let mut stats = TriangleStats {
+            count: match &self.weights {
+                Some(weights) => weights[b] - weights[a],
+                None => (x1 - x0) as f64,
+            },
+            ..Default::default()
+        };
+        for c in 0..3 {
+            stats.sum[c] = self.sums[b][c] - self.sums[a][c];
+            stats.sq_sum[c] = self.sq_sums[b][c] - self.sq_sums[a][c];
+        }
+        stats
+    }
+
+    /// calls `f(y, x0, x1, coverage)` for every run of pixels in the triangle (see [`raster`](super::raster) for which
+    /// pixels those are), where `coverage` is how much each of them counts (1, unless it is a single pixel that is
+    /// only partly covered with [`SamplingMode::Coverage`])
+    fn for_each_run(&self, tri: Triangle, mut f: impl FnMut(u32, u32, u32, f64)) {
+        match self.sampling {
+            SamplingMode::Center => {
+                for Span { y, x0, x1 } in Spans::new(tri, self.width, self.height) {
+                    f(y, x0, x1, 1.0);
+                }
+            }
+            SamplingMode::Coverage => for_each_covered(tri, self.width, self.height, |covered| match covered {
+                Covered::Full(Span { y, x0, x1 }) => f(y, x0, x1, 1.0),
+                Covered::Partial { x, y, coverage } => f(y, x, x + 1, coverage),
+            }),
+        }
+    }
+
+    /// stats for every pixel in the triangle (see [`raster`](super::raster) for which pixels those are).
+    ///
+    /// with [`SamplingMode::Coverage`] partly covered pixels are weighted by how much of them is covered,
+    /// so `count` is the area of the triangle (inside the image) rather than a number of pixels
+    pub fn triangle(&self, tri: Triangle) -> TriangleStats {
+        let mut stats = TriangleStats::default();
+        self.for_each_run(tri, |y, x0, x1, coverage| stats += self.span(y, x0, x1).scaled(coverage));
+        stats
+    }
+
+    /// calls `f` with the value of every pixel in the triangle (the same ones as [`ImageStats::triangle`]), and how
+    /// much it counts (1, unless it is only partly covered with [`SamplingMode::Coverage`], or weighted by the
+    /// weight map).
+    ///
+    /// this is much slower than [`ImageStats::triangle`], so it is only for things that can not be found from the sums
+    pub fn for_each_pixel(&self, tri: Triangle, mut f: impl FnMut([f64; 3], f64)) {
+        self.for_each_run(tri, |y, x0, x1, coverage| {
+            let row = y as usize * self.width as usize;
+            for x in x0..x1 {
+                let weight = match &self.weights {
+                    // the mask is only kept as running sums, so this is the difference of two of them
+                    Some(weights) => {
+                        let i = row + y as usize + x as usize;
+                        weights[i + 1] - weights[i]
+                    }
+                    None => 1.0,
+                };
+                f(self.pixels[row + x as usize].map(f64::from), coverage * weight);
+            }
+        });
+    }
+
+    /// the average color of the triangle (taken in [`color_space`](Self::color_space)), or `None` if it covers no pixels
+    pub fn color(&self, tri: Triangle) -> Option<Rgb<u8>> {
+        let stats = self.triangle(tri);
+        (!stats.is_empty()).then(|| self.space.decode(stats.mean()))
+    }
+}
+
+/// sums over a set of pixels, from which the mean and variance of each channel can be found
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TriangleStats {
+    /// number of pixels (or covered area, when weighted by coverage, and total importance with a weight map)
+    pub count: f64,
+    pub sum: [f64; 3],
+    pub sq_sum: [f64; 3],
+}
+
+impl TriangleStats {
+    pub fn is_empty(&self) -> bool {
+        self.count <= 0.0
+    }
+
+    /// mean of each channel, or zero if there are no pixels
+    pub fn mean(&self) -> [f64; 3] {
+        if self.is_empty() {
+            return [0.0; 3];
+        }
+        self.sum.map(|s| s / self.count)
+    }
+
+    /// (population) variance of each channel, or zero if there are no pixels
+    pub fn variance(&self) -> [f64; 3] {
+        if self.is_empty() {
+            return [0.0; 3];
+        }
+        let mean = self.mean();
+        let mut var = [0.0; 3];
+        for c in 0..3 {
+            // can go very slightly negative from rounding error
+            var[c] = (self.sq_sum[c] / self.count - mean[c] * mean[c]).max(0.0);
+        }
+        var
+    }
+
+    /// estimate of how far from the mean the top `fraction` of pixels are (in the channel that varies the most),
+    /// assuming the values are normally distributed
+    pub fn deviation_above(&self, fraction: f64) -> f64 {
+        if self.is_empty() || fraction >= 1.0 {
+            return 0.0;
+        }
+        let std_dev = self.variance().into_iter().fold(0.0, f64::max).sqrt();
+        // two tailed, since this is the absolute deviation
+        std_dev * normal_upper_quantile(fraction.max(f64::MIN_POSITIVE) / 2.0)
+    }
+
+    /// every pixel weighted by `weight`
+    pub fn scaled(mut self, weight: f64) -> Self {
+        self.count *= weight;
+        for c in 0..3 {
+            self.sum[c] *= weight;
+            self.sq_sum[c] *= weight;
+        }
+        self
+    }
+}
+
+impl std::ops::AddAssign for TriangleStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.count += rhs.count;
+        for c in 0..3 {
+            self.sum[c] += rhs.sum[c];
+            self.sq_sum[c] += rhs.sq_sum[c];
+        }
+    }
+}
+
+/// the `z` where a standard normal variable is greater than `z` with probability `p` (for `0 < p <= 0.5`)
+///
+/// uses the rational approximation from Abramowitz and Stegun 26.2.23, which is accurate to about 4.5e-4
+fn normal_upper_quantile(p: f64) -> f64 {
+    let t = (-2.0 * p.ln()).sqrt();
+    t - (2.515517 + 0.802853 * t + 0.010328 * t * t) / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)
+}
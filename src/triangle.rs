@@ -1,9 +1,10 @@
 use opengl_graphics::GlGraphics;
+use serde::{Deserialize, Serialize};
 
 use crate::colors::Color;
 use crate::vec2::F64x2;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Triangles {
     vbuf: Vec<Vec<F64x2>>,
     scale_size: (u32, u32), // MAY NOT CORRISPOND TO vbuf sizes!
@@ -22,6 +23,46 @@ impl Triangles {
         }
     }
 
+    /// creates a grid covering an image of the given size, padded out to fit a whole number of triangles
+    pub fn for_image(width: u32, height: u32, size: f64) -> Self {
+        Self::new(
+            width + (size - width as f64 % size.ceil()) as u32,
+            height + (size - height as f64 % size.ceil()) as u32,
+            size,
+        )
+    }
+
+    /// rebuilds a grid from its raw parts (see [`Triangles::rows`], [`Triangles::scale_size`],
+    /// [`Triangles::real_size`], and [`Triangles::size_of_chunk`])
+    pub fn from_parts(vbuf: Vec<Vec<F64x2>>, scale_size: (u32, u32), real_size: (u32, u32), size_of_chunk: f64) -> Self {
+        Self {
+            vbuf,
+            scale_size,
+            real_size,
+            size_of_chunk,
+        }
+    }
+
+    /// the rows of verticies making up the grid
+    pub fn rows(&self) -> &[Vec<F64x2>] {
+        &self.vbuf
+    }
+
+    /// size of the grid in triangles (SCALE units)
+    pub fn scale_size(&self) -> (u32, u32) {
+        self.scale_size
+    }
+
+    /// size of the area the grid was created to cover, in pixels
+    pub fn real_size(&self) -> (u32, u32) {
+        self.real_size
+    }
+
+    /// size of each triangle this grid was created with
+    pub fn size_of_chunk(&self) -> f64 {
+        self.size_of_chunk
+    }
+
     pub fn triangles_around_point(&self, x: u32, y: u32) -> Vec<Triangle> {
         self.triangle_locations_around_point(x, y)
             .into_iter()
@@ -53,9 +94,7 @@ impl Triangles {
         ];
         perms
             .into_iter()
-            .map(|p| get_if_exists(p.0, p.1))
-            .filter(|o| o.is_some())
-            .map(|o| o.unwrap())
+            .filter_map(|p| get_if_exists(p.0, p.1))
             .map(|(b, c)| [(x, y), b, c])
             .collect()
     }
@@ -77,9 +116,91 @@ impl Triangles {
         Some((x, y))
     }
 
+    /// a unique index for a triangle in the grid (given as the locations of its corners, in any order),
+    /// less than [`Triangles::triangle_count`]
+    pub fn triangle_index(&self, tri: &[(u32, u32); 3]) -> usize {
+        // every triangle is either (v, Right, DownRight) or (v, DownRight, DownLeft), where v is the top left corner
+        let (x, y) = *tri.iter().min_by_key(|(x, y)| (*y, *x)).unwrap();
+        let points_down = tri.iter().filter(|(_, ty)| *ty == y).count() == 1;
+        (y as usize * self.max_row_len() + x as usize) * 2 + points_down as usize
+    }
+
+    /// upper bound on [`Triangles::triangle_index`]
+    pub fn triangle_count(&self) -> usize {
+        self.vbuf.len() * self.max_row_len() * 2
+    }
+
+    /// every triangle in the grid, each exactly once
+    pub fn all_triangles(&self) -> Vec<Triangle> {
+        use RelVertPos::*;
+
+        let mut tris = vec![];
+        for (y, row) in self.vbuf.iter().enumerate() {
+            for (x, vert) in row.iter().enumerate() {
+                let (x, y) = (x as u32, y as u32);
+                for (a, b) in [(DownRight, DownLeft), (Right, DownRight)] {
+                    if let (Some(a), Some(b)) = (self.pos_rel(x, y, a), self.pos_rel(x, y, b)) {
+                        tris.push(Triangle(*vert, *self.get_vert(a.0, a.1), *self.get_vert(b.0, b.1)));
+                    }
+                }
+            }
+        }
+        tris
+    }
+
+    fn max_row_len(&self) -> usize {
+        self.vbuf.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// verticies that share an edge with (x, y)
+    pub fn neighbours(&self, x: u32, y: u32) -> Vec<(u32, u32)> {
+        use RelVertPos::*;
+        [UpRight, Right, DownRight, DownLeft, Left, UpLeft]
+            .into_iter()
+            .filter_map(|pos| self.pos_rel(x, y, pos))
+            .collect()
+    }
+
+    /// splits all of the verticies into sets where no two verticies in the same set are neighbours.
+    ///
+    /// moving a vertex only changes the triangles around it, so the verticies in one set
+    /// can all be optimized at the same time without affecting each other.
+    pub fn independent_sets(&self) -> Vec<Vec<(u32, u32)>> {
+        // greedy coloring, which only needs 3 colors for a grid like this one
+        let mut colors: Vec<Vec<Option<usize>>> = self.vbuf.iter().map(|row| vec![None; row.len()]).collect();
+        let mut sets: Vec<Vec<(u32, u32)>> = vec![];
+        for (y, row) in self.vbuf.iter().enumerate() {
+            for x in 0..row.len() {
+                let (x, y) = (x as u32, y as u32);
+                let taken = self
+                    .neighbours(x, y)
+                    .into_iter()
+                    .filter_map(|(nx, ny)| colors[ny as usize][nx as usize])
+                    .collect::<Vec<_>>();
+                let color = (0..).find(|c| !taken.contains(c)).unwrap();
+                colors[y as usize][x as usize] = Some(color);
+                if color >= sets.len() {
+                    sets.push(vec![]);
+                }
+                sets[color].push((x, y));
+            }
+        }
+        sets
+    }
+
     pub fn vert_is_edge(&self, x: u32, y: u32) -> bool {
+        self.vert_is_side(x, y) || self.vert_is_top_or_bottom(y)
+    }
+
+    /// if the vertex is at the start or end of its row
+    pub fn vert_is_side(&self, x: u32, y: u32) -> bool {
         let o = if y % 2 == 1 { 0 } else { 1 };
-        x == 0 || x >= self.scale_size.0 + o || y == 0 || y >= self.scale_size.1
+        x == 0 || x >= self.scale_size.0 + o
+    }
+
+    /// if the vertex is in the first or last row
+    pub fn vert_is_top_or_bottom(&self, y: u32) -> bool {
+        y == 0 || y >= self.scale_size.1
     }
 
     /// x and y are in SCALE units
@@ -89,7 +210,7 @@ impl Triangles {
 
     /// x and y are in SCALE units
     pub fn try_get_vert(&self, x: u32, y: u32) -> Option<&F64x2> {
-        Some(self.vbuf.get(y as usize)?.get(x as usize)?)
+        self.vbuf.get(y as usize)?.get(x as usize)
     }
 
     /// x and y are in SCALE units
@@ -99,7 +220,7 @@ impl Triangles {
 
     /// x and y are in SCALE units
     pub fn try_get_vert_mut(&mut self, x: u32, y: u32) -> Option<&mut F64x2> {
-        Some(self.vbuf.get_mut(y as usize)?.get_mut(x as usize)?)
+        self.vbuf.get_mut(y as usize)?.get_mut(x as usize)
     }
 
     pub fn into_iter_verts(self) -> impl Iterator<Item = (u32, u32, F64x2)> {
@@ -174,6 +295,31 @@ pub fn generate_regular_points(width: u32, height: u32, size: f64) -> (u32, u32,
 pub struct Triangle(pub F64x2, pub F64x2, pub F64x2);
 
 impl Triangle {
+    pub fn area(&self) -> f64 {
+        self.signed_area().abs()
+    }
+
+    /// area, which is positive if the corners are in the winding order used by meshes (clockwise on screen),
+    /// and negative if the triangle has been turned inside out
+    pub fn signed_area(&self) -> f64 {
+        (self.1 - self.0).cross_2v(self.2 - self.0) / 2.0
+    }
+
+    /// smallest interior angle, in degrees (0 for a degenerate triangle)
+    pub fn min_angle(&self) -> f64 {
+        let angle = |at: F64x2, a: F64x2, b: F64x2| {
+            let (u, v) = (a - at, b - at);
+            let len = u.length() * v.length();
+            if len == 0.0 {
+                return 0.0;
+            }
+            (u.dot(v) / len).clamp(-1.0, 1.0).acos().to_degrees()
+        };
+        angle(self.0, self.1, self.2)
+            .min(angle(self.1, self.2, self.0))
+            .min(angle(self.2, self.0, self.1))
+    }
+
     pub fn offset(mut self, x: f64, y: f64) -> Self {
         self.0.x += x;
         self.1.x += x;
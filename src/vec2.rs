@@ -2,7 +2,9 @@ use std::ops::{
     Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
 };
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct F64x2 {
     pub x: f64,
     pub y: f64,
@@ -21,6 +23,15 @@ impl F64x2 {
         F64x2::new(0.0, 0.0)
     }
 
+    /// dot product of two vectors
+    pub fn dot(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
     /// cross product of two vectors
     pub fn cross_2v(self, other: Self) -> f64 {
         self.x * other.y - self.y * other.x